@@ -0,0 +1,401 @@
+use crate::{
+    command::{CommandRequest, CommandRunner, DEFAULT_COMMAND_TIMEOUT},
+    emit,
+    modifiers::Modifier,
+};
+use input_linux::{EventKind, Key, SynchronizeKind, uinput::UInputHandle};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    mem,
+    time::{Duration, Instant},
+};
+
+const REPEAT_DELAY: Duration = Duration::from_millis(500);
+// Repeats per second
+const REPEAT_RATE: u32 = 30;
+
+// One step of a sequence
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Step {
+    Key(Key),
+    Chord(Vec<Key>),
+    // Milliseconds to wait before the next step
+    Delay {
+        #[serde(rename = "Delay")]
+        delay: u64,
+    },
+}
+
+// Moves between named layers. Pushed layers are shown instead of the primary
+// layer until they are popped, switching replaces the last pushed layer.
+#[derive(Deserialize, Clone, Debug)]
+pub enum LayerAction {
+    #[serde(rename = "PushLayer")]
+    Push(String),
+    #[serde(rename = "PopLayer")]
+    Pop,
+    #[serde(rename = "SwitchLayer")]
+    Switch(String),
+}
+
+impl LayerAction {
+    pub fn layer_name(&self) -> Option<&str> {
+        match self {
+            LayerAction::Push(name) | LayerAction::Switch(name) => Some(name),
+            LayerAction::Pop => None,
+        }
+    }
+}
+
+// What a button sends. A key or chord is held down for as long as the button is,
+// a sequence is played, a command run and layers changed once when the button
+// is pressed.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Action {
+    Key(Key),
+    Chord(Vec<Key>),
+    Sequence {
+        #[serde(rename = "Sequence")]
+        sequence: Vec<Step>,
+    },
+    #[serde(rename_all = "PascalCase")]
+    Command {
+        command: Vec<String>,
+        // Milliseconds before the command is killed
+        timeout: Option<u64>,
+        flash_on_failure: Option<bool>,
+    },
+    Layer(LayerAction),
+}
+
+// Whether a held button sends its key again, either true or false, or the Delay in
+// milliseconds before the first repeat and the Rate in repeats per second
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(untagged)]
+pub enum RepeatConfig {
+    Enabled(bool),
+    #[serde(rename_all = "PascalCase")]
+    Custom {
+        delay: Option<u64>,
+        rate: Option<u32>,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Repeat {
+    pub delay: Duration,
+    pub interval: Duration,
+}
+
+impl RepeatConfig {
+    pub fn resolve(self) -> Option<Repeat> {
+        let (delay, rate) = match self {
+            RepeatConfig::Enabled(false) => return None,
+            RepeatConfig::Enabled(true) => (None, None),
+            RepeatConfig::Custom { delay, rate } => (delay, rate),
+        };
+        let rate = rate.unwrap_or(REPEAT_RATE);
+        if rate == 0 {
+            panic!("Invalid configuration, Repeat Rate must be above 0");
+        }
+        Some(Repeat {
+            delay: delay.map_or(REPEAT_DELAY, Duration::from_millis),
+            interval: Duration::from_secs(1) / rate,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum KeyEvent {
+    Press(Key),
+    Release(Key),
+    Wait(Duration),
+    Run(CommandRequest),
+    Layer(LayerAction),
+    // Start repeating a key that was just pressed
+    Repeat(Key, Repeat),
+}
+
+// The keys of a chord with the modifiers moved to the front, whatever order they were written in
+fn modifiers_first(keys: &[Key]) -> Vec<Key> {
+    let mut keys = keys.to_vec();
+    keys.sort_by_key(|&k| Modifier::from_key(k as u32).is_none());
+    keys
+}
+
+// Modifiers go down first and come up last
+fn chord_down(keys: &[Key]) -> impl Iterator<Item = KeyEvent> {
+    modifiers_first(keys).into_iter().map(KeyEvent::Press)
+}
+
+fn chord_up(keys: &[Key]) -> impl Iterator<Item = KeyEvent> {
+    modifiers_first(keys)
+        .into_iter()
+        .rev()
+        .map(KeyEvent::Release)
+}
+
+impl Action {
+    pub fn press(&self) -> Vec<KeyEvent> {
+        match self {
+            Action::Key(key) => vec![KeyEvent::Press(*key)],
+            Action::Chord(keys) => chord_down(keys).collect(),
+            Action::Sequence { sequence } => sequence
+                .iter()
+                .flat_map(|step| match step {
+                    Step::Key(key) => vec![KeyEvent::Press(*key), KeyEvent::Release(*key)],
+                    Step::Chord(keys) => chord_down(keys).chain(chord_up(keys)).collect(),
+                    Step::Delay { delay } => vec![KeyEvent::Wait(Duration::from_millis(*delay))],
+                })
+                .collect(),
+            Action::Command {
+                command, timeout, ..
+            } => vec![KeyEvent::Run(CommandRequest {
+                argv: command.clone(),
+                timeout: timeout.map_or(DEFAULT_COMMAND_TIMEOUT, Duration::from_millis),
+            })],
+            Action::Layer(action) => vec![KeyEvent::Layer(action.clone())],
+        }
+    }
+    pub fn release(&self) -> Vec<KeyEvent> {
+        match self {
+            Action::Key(key) => vec![KeyEvent::Release(*key)],
+            Action::Chord(keys) => chord_up(keys).collect(),
+            Action::Sequence { .. } | Action::Command { .. } | Action::Layer(_) => Vec::new(),
+        }
+    }
+    // Press and release, for actions that don't follow a touch
+    pub fn tap(&self) -> Vec<KeyEvent> {
+        let mut events = self.press();
+        events.extend(self.release());
+        events
+    }
+    // Every key this may send
+    pub fn keys(&self) -> Vec<Key> {
+        match self {
+            Action::Key(key) => vec![*key],
+            Action::Chord(keys) => keys.clone(),
+            Action::Sequence { sequence } => sequence
+                .iter()
+                .flat_map(|step| match step {
+                    Step::Key(key) => vec![*key],
+                    Step::Chord(keys) => keys.clone(),
+                    Step::Delay { .. } => Vec::new(),
+                })
+                .collect(),
+            Action::Command { .. } | Action::Layer(_) => Vec::new(),
+        }
+    }
+    // The key sent again while a repeating button is held, for chords the last
+    // one, as a keyboard would with the modifiers held
+    pub fn repeat_key(&self) -> Option<Key> {
+        match self {
+            Action::Key(key) => Some(*key),
+            Action::Chord(keys) => modifiers_first(keys).last().copied(),
+            _ => None,
+        }
+    }
+    // Whether a failure of this command should be shown on its button
+    pub fn flashes_on_failure(&self, argv: &[String]) -> bool {
+        match self {
+            Action::Command {
+                command,
+                flash_on_failure,
+                ..
+            } => command == argv && flash_on_failure.unwrap_or(true),
+            _ => false,
+        }
+    }
+}
+
+// A key sent again every interval once the delay has passed, until it is released
+// or another key is pressed. libinput drops the kernel's own repeat events, so the
+// key is released and pressed again instead.
+struct Repeating {
+    key: Key,
+    interval: Duration,
+    next: Instant,
+}
+
+// Where actions go, the uinput device for keys and the helper for commands.
+// Layer changes are left for the main loop to pick up. Events are played in
+// order, holding back whatever comes after a delay until it has passed.
+pub struct VirtualKeyboard {
+    pub uinput: UInputHandle<File>,
+    pub commands: CommandRunner,
+    queue: VecDeque<KeyEvent>,
+    resume_at: Option<Instant>,
+    layer_actions: Vec<LayerAction>,
+    repeating: Option<Repeating>,
+}
+
+impl VirtualKeyboard {
+    pub fn new(uinput: UInputHandle<File>, commands: CommandRunner) -> VirtualKeyboard {
+        VirtualKeyboard {
+            uinput,
+            commands,
+            queue: VecDeque::new(),
+            resume_at: None,
+            layer_actions: Vec::new(),
+            repeating: None,
+        }
+    }
+    pub fn send(&mut self, events: impl IntoIterator<Item = KeyEvent>) {
+        self.queue.extend(events);
+        self.run();
+    }
+    pub fn tap(&mut self, key: Key) {
+        self.send([KeyEvent::Press(key), KeyEvent::Release(key)]);
+    }
+    fn emit_key(&mut self, key: Key, value: i32) {
+        emit(&mut self.uinput, EventKind::Key, key as u16, value);
+        emit(
+            &mut self.uinput,
+            EventKind::Synchronize,
+            SynchronizeKind::Report as u16,
+            0,
+        );
+    }
+    // Play queued events up to the next delay that hasn't passed yet
+    pub fn run(&mut self) {
+        let now = Instant::now();
+        if let Some(repeating) = self.repeating.as_mut()
+            && repeating.next <= now
+        {
+            repeating.next = now + repeating.interval;
+            let key = repeating.key;
+            self.emit_key(key, 0);
+            self.emit_key(key, 1);
+        }
+        if self.resume_at.is_some_and(|t| t > now) {
+            return;
+        }
+        self.resume_at = None;
+        while let Some(event) = self.queue.pop_front() {
+            let (key, value) = match event {
+                KeyEvent::Press(key) => {
+                    if self.repeating.as_ref().is_some_and(|r| r.key != key) {
+                        self.repeating = None;
+                    }
+                    (key, 1)
+                }
+                KeyEvent::Release(key) => {
+                    if self.repeating.as_ref().is_some_and(|r| r.key == key) {
+                        self.repeating = None;
+                    }
+                    (key, 0)
+                }
+                KeyEvent::Wait(delay) => {
+                    self.resume_at = Some(Instant::now() + delay);
+                    return;
+                }
+                KeyEvent::Run(request) => {
+                    self.commands.run(&request);
+                    continue;
+                }
+                KeyEvent::Layer(action) => {
+                    self.layer_actions.push(action);
+                    continue;
+                }
+                KeyEvent::Repeat(key, repeat) => {
+                    self.repeating = Some(Repeating {
+                        key,
+                        interval: repeat.interval,
+                        next: Instant::now() + repeat.delay,
+                    });
+                    continue;
+                }
+            };
+            self.emit_key(key, value);
+        }
+    }
+    // When run should next be called
+    pub fn deadline(&self) -> Option<Instant> {
+        self.resume_at
+            .into_iter()
+            .chain(self.repeating.as_ref().map(|r| r.next))
+            .min()
+    }
+    // Layer changes sent since the last call
+    pub fn take_layer_actions(&mut self) -> Vec<LayerAction> {
+        mem::take(&mut self.layer_actions)
+    }
+    // Drop anything still waiting to be sent
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.resume_at = None;
+        self.layer_actions.clear();
+        self.repeating = None;
+    }
+    // Drop anything queued and let go of the given keys, in case a touch was holding them
+    pub fn release_all(&mut self, keys: &[Key]) {
+        self.clear();
+        for &key in keys {
+            emit(&mut self.uinput, EventKind::Key, key as u16, 0);
+        }
+        emit(
+            &mut self.uinput,
+            EventKind::Synchronize,
+            SynchronizeKind::Report as u16,
+            0,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(events: Vec<KeyEvent>) -> Vec<(bool, Key)> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                KeyEvent::Press(key) => (true, key),
+                KeyEvent::Release(key) => (false, key),
+                other => panic!("unexpected {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chord_modifiers_wrap_the_key() {
+        let chord = Action::Chord(vec![Key::T, Key::LeftShift, Key::LeftCtrl]);
+        assert_eq!(
+            keys(chord.press()),
+            [
+                (true, Key::LeftShift),
+                (true, Key::LeftCtrl),
+                (true, Key::T)
+            ]
+        );
+        assert_eq!(
+            keys(chord.release()),
+            [
+                (false, Key::T),
+                (false, Key::LeftCtrl),
+                (false, Key::LeftShift)
+            ]
+        );
+        assert_eq!(chord.repeat_key(), Some(Key::T));
+    }
+
+    #[test]
+    fn chord_in_sequence_is_ordered() {
+        let action = Action::Sequence {
+            sequence: vec![Step::Chord(vec![Key::V, Key::RightMeta])],
+        };
+        assert_eq!(
+            keys(action.press()),
+            [
+                (true, Key::RightMeta),
+                (true, Key::V),
+                (false, Key::V),
+                (false, Key::RightMeta)
+            ]
+        );
+    }
+}
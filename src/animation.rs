@@ -0,0 +1,221 @@
+use crate::{display::Orientation, theme::Color};
+use cairo::{Context, Format, ImageSurface};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+// Redraw interval while anything is animating
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+// When the next frame is due, frames are only drawn on a timer while animating
+pub fn next_draw_time(animating: bool, now: Instant) -> Option<Instant> {
+    animating.then_some(now + FRAME_INTERVAL)
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum TransitionKind {
+    None,
+    Fade,
+    Slide,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AnimationConfig {
+    pub duration: Duration,
+    pub layer_transition: TransitionKind,
+    pub press_ripple: bool,
+}
+
+// Cubic ease out, fast at the start and settling gently
+fn ease_out(t: f64) -> f64 {
+    1.0 - (1.0 - t).powi(3)
+}
+
+// A value moving between two points over time
+#[derive(Clone, Copy, Debug)]
+pub struct Tween {
+    start: Instant,
+    duration: Duration,
+    from: f64,
+    to: f64,
+}
+
+impl Tween {
+    pub fn new(from: f64, to: f64, duration: Duration) -> Tween {
+        Tween {
+            start: Instant::now(),
+            duration,
+            from,
+            to,
+        }
+    }
+    // A tween that has already reached its value
+    pub fn settled(value: f64) -> Tween {
+        Tween::new(value, value, Duration::ZERO)
+    }
+    pub fn running(&self) -> bool {
+        self.running_at(Instant::now())
+    }
+    pub fn value(&self) -> f64 {
+        self.value_at(Instant::now())
+    }
+    fn running_at(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) < self.duration
+    }
+    fn progress_at(&self, now: Instant) -> f64 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        let elapsed = now.saturating_duration_since(self.start);
+        (elapsed.as_secs_f64() / self.duration.as_secs_f64()).min(1.0)
+    }
+    fn value_at(&self, now: Instant) -> f64 {
+        self.from + (self.to - self.from) * ease_out(self.progress_at(now))
+    }
+    // Head towards a new value, starting from wherever we are now
+    pub fn retarget(&mut self, to: f64, duration: Duration) {
+        *self = Tween::new(self.value(), to, duration);
+    }
+}
+
+// Cross fade or slide from a snapshot of the previous layer to the one being drawn
+pub struct LayerTransition {
+    from: ImageSurface,
+    output: ImageSurface,
+    kind: TransitionKind,
+    progress: Tween,
+    // 1.0 if the new layer comes in from the right, -1.0 from the left
+    direction: f64,
+}
+
+fn copy_surface(surface: &ImageSurface) -> ImageSurface {
+    let copy = ImageSurface::create(Format::ARgb32, surface.width(), surface.height()).unwrap();
+    let c = Context::new(&copy).unwrap();
+    c.set_source_surface(surface, 0.0, 0.0).unwrap();
+    c.paint().unwrap();
+    copy
+}
+
+impl LayerTransition {
+    pub fn new(
+        current: &ImageSurface,
+        cfg: &AnimationConfig,
+        direction: f64,
+    ) -> Option<LayerTransition> {
+        if cfg.layer_transition == TransitionKind::None || cfg.duration.is_zero() {
+            return None;
+        }
+        Some(LayerTransition {
+            from: copy_surface(current),
+            output: copy_surface(current),
+            kind: cfg.layer_transition,
+            progress: Tween::new(0.0, 1.0, cfg.duration),
+            direction,
+        })
+    }
+    pub fn running(&self) -> bool {
+        self.progress.running()
+    }
+    // Blend the previous layer with the freshly drawn next one, returns the frame to present
+    pub fn composite(
+        &mut self,
+        next: &ImageSurface,
+        background: Color,
+        orientation: &Orientation,
+        width: f64,
+        height: f64,
+    ) -> &mut ImageSurface {
+        let t = self.progress.value();
+        let c = Context::new(&self.output).unwrap();
+        // Uncovered while sliding
+        background.set_source(&c);
+        c.paint().unwrap();
+        match self.kind {
+            TransitionKind::Slide => {
+                // Both snapshots are in scanout coordinates, so slide them in layout space
+                let layout_to_buffer = orientation.matrix(width, height);
+                let buffer_to_layout = layout_to_buffer.try_invert().unwrap();
+                for (surface, offset) in [
+                    (&self.from, -t * width * self.direction),
+                    (next, (1.0 - t) * width * self.direction),
+                ] {
+                    c.save().unwrap();
+                    c.transform(layout_to_buffer);
+                    c.translate(offset, 0.0);
+                    c.transform(buffer_to_layout);
+                    c.set_source_surface(surface, 0.0, 0.0).unwrap();
+                    c.paint().unwrap();
+                    c.restore().unwrap();
+                }
+            }
+            _ => {
+                c.set_source_surface(&self.from, 0.0, 0.0).unwrap();
+                c.paint().unwrap();
+                c.set_source_surface(next, 0.0, 0.0).unwrap();
+                c.paint_with_alpha(t).unwrap();
+            }
+        }
+        drop(c);
+        &mut self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn tween(from: f64, to: f64, start: Instant, duration: Duration) -> Tween {
+        Tween {
+            start,
+            duration,
+            from,
+            to,
+        }
+    }
+
+    #[test]
+    fn eases_out() {
+        assert_eq!(ease_out(0.0), 0.0);
+        assert_eq!(ease_out(0.5), 0.875);
+        assert_eq!(ease_out(1.0), 1.0);
+    }
+
+    #[test]
+    fn tween_progresses_and_completes() {
+        let start = Instant::now();
+        let t = tween(10.0, 20.0, start, 200 * MS);
+        assert!(t.running_at(start));
+        assert_eq!(t.progress_at(start), 0.0);
+        assert_eq!(t.value_at(start), 10.0);
+        assert_eq!(t.progress_at(start + 100 * MS), 0.5);
+        assert_eq!(t.value_at(start + 100 * MS), 18.75);
+        assert!(t.running_at(start + 199 * MS));
+        assert!(!t.running_at(start + 200 * MS));
+        assert_eq!(t.progress_at(start + 200 * MS), 1.0);
+        assert_eq!(t.value_at(start + 1000 * MS), 20.0);
+    }
+
+    #[test]
+    fn settled_tween_is_done() {
+        let t = Tween::settled(0.3);
+        assert!(!t.running());
+        assert_eq!(t.progress_at(Instant::now()), 1.0);
+        assert_eq!(t.value(), 0.3);
+    }
+
+    #[test]
+    fn retarget_starts_from_current_value() {
+        let mut t = tween(0.0, 1.0, Instant::now() - 1000 * MS, 200 * MS);
+        t.retarget(0.0, 200 * MS);
+        assert_eq!((t.from, t.to), (1.0, 0.0));
+        assert!(t.running());
+    }
+
+    #[test]
+    fn frames_only_while_animating() {
+        let now = Instant::now();
+        assert_eq!(next_draw_time(false, now), None);
+        assert_eq!(next_draw_time(true, now), Some(now + FRAME_INTERVAL));
+    }
+}
@@ -17,6 +17,10 @@ const MAX_TOUCH_BAR_BRIGHTNESS: u32 = 255;
 const BRIGHTNESS_DIM_TIMEOUT: Duration = Duration::from_secs(15); // should be a multiple of TIMEOUT_MS
 const BRIGHTNESS_OFF_TIMEOUT: Duration = Duration::from_secs(60); // should be a multiple of TIMEOUT_MS
 
+fn try_read_attr(path: &Path, attr: &str) -> Result<u32> {
+    Ok(fs::read_to_string(path.join(attr))?.trim().parse::<u32>()?)
+}
+
 fn read_attr(path: &Path, attr: &str) -> u32 {
     fs::read_to_string(path.join(attr))
         .unwrap_or_else(|_| panic!("Failed to read {attr}"))
@@ -41,8 +45,10 @@ fn find_backlight() -> Result<PathBuf> {
     Err(anyhow!("No Touch Bar backlight device found"))
 }
 
-fn find_display_backlight() -> Result<PathBuf> {
-    for entry in fs::read_dir("/sys/class/backlight/")? {
+pub const SYSFS_ROOT: &str = "/sys";
+
+fn find_display_backlight_in(sysfs: &Path) -> Result<PathBuf> {
+    for entry in fs::read_dir(sysfs.join("class/backlight"))? {
         let entry = entry?;
         if [
             "apple-panel-bl",
@@ -59,8 +65,91 @@ fn find_display_backlight() -> Result<PathBuf> {
     Err(anyhow!("No Built-in Retina Display backlight device found"))
 }
 
-fn set_backlight(mut file: &File, value: u32) {
-    file.write_all(format!("{}\n", value).as_bytes()).unwrap();
+fn find_display_backlight() -> Result<PathBuf> {
+    find_display_backlight_in(Path::new(SYSFS_ROOT))
+}
+
+fn find_keyboard_backlight_in(sysfs: &Path) -> Result<PathBuf> {
+    for entry in fs::read_dir(sysfs.join("class/leds"))? {
+        let entry = entry?;
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .ends_with("kbd_backlight")
+        {
+            return Ok(entry.path());
+        }
+    }
+    Err(anyhow!("No keyboard backlight device found"))
+}
+
+// Which brightness a slider controls, sysfs paths are relative to the sysfs root
+pub enum BrightnessDevice {
+    Display,
+    Keyboard,
+    Path(PathBuf),
+}
+
+// A backlight or LED brightness attribute that can be read and written as a fraction
+pub struct SysfsBrightness {
+    path: PathBuf,
+    max: u32,
+    // Lowest value written, so the display backlight never turns off entirely
+    min: u32,
+}
+
+impl SysfsBrightness {
+    pub fn open(sysfs: &Path, device: &BrightnessDevice) -> Result<SysfsBrightness> {
+        let (path, min) = match device {
+            BrightnessDevice::Display => (find_display_backlight_in(sysfs)?, 1),
+            BrightnessDevice::Keyboard => (find_keyboard_backlight_in(sysfs)?, 0),
+            BrightnessDevice::Path(path) => {
+                (sysfs.join(path.strip_prefix("/sys").unwrap_or(path)), 0)
+            }
+        };
+        let max = try_read_attr(&path, "max_brightness")?;
+        if max == 0 {
+            return Err(anyhow!("{} has no brightness levels", path.display()));
+        }
+        Ok(SysfsBrightness {
+            path,
+            max,
+            min: min.min(max),
+        })
+    }
+    pub fn get(&self) -> Result<f64> {
+        Ok(try_read_attr(&self.path, "brightness")? as f64 / self.max as f64)
+    }
+    pub fn set(&self, level: f64) -> Result<()> {
+        let value = ((level.clamp(0.0, 1.0) * self.max as f64).round() as u32).max(self.min);
+        let file = OpenOptions::new()
+            .write(true)
+            .open(self.path.join("brightness"))?;
+        set_backlight(&file, value)
+    }
+}
+
+fn open_backlight() -> Result<File> {
+    Ok(OpenOptions::new()
+        .write(true)
+        .open(find_backlight()?.join("brightness"))?)
+}
+
+// Write through a file that may have gone stale, opening the backlight again if so
+fn write_backlight(file: &mut Option<File>, value: u32) -> Result<()> {
+    if let Some(file) = file
+        && set_backlight(file, value).is_ok()
+    {
+        return Ok(());
+    }
+    let reopened = open_backlight()?;
+    set_backlight(&reopened, value)?;
+    *file = Some(reopened);
+    Ok(())
+}
+
+fn set_backlight(mut file: &File, value: u32) -> Result<()> {
+    Ok(file.write_all(format!("{}\n", value).as_bytes())?)
 }
 
 pub struct BacklightManager {
@@ -68,26 +157,24 @@ pub struct BacklightManager {
     max_bl: u32,
     current_bl: u32,
     lid_state: SwitchState,
-    bl_file: File,
+    // Reopened when a write through it fails, the device comes and goes with the display
+    bl_file: Option<File>,
     display_bl_path: PathBuf,
 }
 
 impl BacklightManager {
-    pub fn new() -> BacklightManager {
-        let bl_path = find_backlight().unwrap();
-        let display_bl_path = find_display_backlight().unwrap();
-        let bl_file = OpenOptions::new()
-            .write(true)
-            .open(bl_path.join("brightness"))
-            .unwrap();
-        BacklightManager {
-            bl_file,
+    pub fn new() -> Result<BacklightManager> {
+        let bl_path = find_backlight()?;
+        let display_bl_path = find_display_backlight()?;
+        let bl_file = open_backlight()?;
+        Ok(BacklightManager {
+            bl_file: Some(bl_file),
             lid_state: SwitchState::Off,
-            max_bl: read_attr(&bl_path, "max_brightness"),
-            current_bl: read_attr(&bl_path, "brightness"),
+            max_bl: try_read_attr(&bl_path, "max_brightness")?,
+            current_bl: try_read_attr(&bl_path, "brightness")?,
             last_active: Instant::now(),
             display_bl_path,
-        }
+        })
     }
     fn display_to_touchbar(display: u32, active_brightness: u32) -> u32 {
         let normalized = display as f64 / MAX_DISPLAY_BRIGHTNESS as f64;
@@ -135,10 +222,93 @@ impl BacklightManager {
         );
         if self.current_bl != new_bl {
             self.current_bl = new_bl;
-            set_backlight(&self.bl_file, self.current_bl);
+            // The backlight device disappears together with the display on USB resets,
+            // so the old file handle may be stale
+            if let Err(err) = write_backlight(&mut self.bl_file, new_bl) {
+                println!("Failed to set touch bar backlight: {err}");
+            }
         }
     }
     pub fn current_bl(&self) -> u32 {
         self.current_bl
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    // A fake sysfs root with the given brightness devices, as (class/name, max_brightness)
+    fn fake_sysfs(devices: &[(&str, u32)]) -> TempDir {
+        let root = TempDir::new("sysfs");
+        for (device, max) in devices {
+            let dir = root.join("class").join(device);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("max_brightness"), format!("{max}\n")).unwrap();
+            fs::write(dir.join("brightness"), "0\n").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn finds_display_backlight() {
+        let root = fake_sysfs(&[
+            ("backlight/appletb_backlight", 255),
+            ("backlight/intel_backlight", 1000),
+        ]);
+        assert_eq!(
+            find_display_backlight_in(&root).unwrap(),
+            root.join("class/backlight/intel_backlight")
+        );
+
+        let root = fake_sysfs(&[("backlight/appletb_backlight", 255)]);
+        assert!(find_display_backlight_in(&root).is_err());
+    }
+
+    #[test]
+    fn finds_keyboard_backlight() {
+        let root = fake_sysfs(&[
+            ("leds/input3::capslock", 1),
+            ("leds/apple::kbd_backlight", 255),
+        ]);
+        assert_eq!(
+            find_keyboard_backlight_in(&root).unwrap(),
+            root.join("class/leds/apple::kbd_backlight")
+        );
+        assert!(SysfsBrightness::open(&root, &BrightnessDevice::Display).is_err());
+
+        let root = fake_sysfs(&[("leds/input3::capslock", 1)]);
+        assert!(find_keyboard_backlight_in(&root).is_err());
+    }
+
+    #[test]
+    fn set_clamps_to_min() {
+        let root = fake_sysfs(&[("backlight/intel_backlight", 1000)]);
+        // Writes don't truncate the fake attribute like they would in sysfs, so only read one line
+        let attr = root.join("class/backlight/intel_backlight/brightness");
+        let read = || -> u32 {
+            fs::read_to_string(&attr)
+                .unwrap()
+                .lines()
+                .next()
+                .unwrap()
+                .parse()
+                .unwrap()
+        };
+        let display = SysfsBrightness::open(&root, &BrightnessDevice::Display).unwrap();
+        display.set(0.0).unwrap();
+        assert_eq!(read(), 1);
+        display.set(0.5).unwrap();
+        assert_eq!(read(), 500);
+        assert_eq!(display.get().unwrap(), 0.5);
+        display.set(2.0).unwrap();
+        assert_eq!(read(), 1000);
+
+        // Other devices may go all the way down
+        let path = BrightnessDevice::Path("/sys/class/backlight/intel_backlight".into());
+        let raw = SysfsBrightness::open(&root, &path).unwrap();
+        raw.set(0.0).unwrap();
+        assert_eq!(read(), 0);
+    }
+}
@@ -0,0 +1,137 @@
+// Command line client for the tiny-dfr control socket
+use anyhow::{Result, anyhow};
+use std::{
+    env,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    process,
+};
+
+#[path = "../control/protocol.rs"]
+mod protocol;
+
+use protocol::{Command, LayerRef, PROTOCOL_VERSION, Request, Response, SOCKET_PATH};
+
+const USAGE: &str = "Usage: tiny-dfr-ctl <command>
+
+Commands:
+    state                                 Print the layers and buttons as JSON
+    switch-layer <layer>                  Show another layer
+    set-text <layer> <button> <text>      Change the label of a button
+    set-icon <layer> <button> <icon> [theme]
+                                          Change the icon of a button
+    fn-mode <hold|toggle|lock> [primary|fn]
+                                          Change how Fn switches layers, and
+                                          which one is shown by default
+    reload                                Load the config file again
+    screenshot <file>                     Save what the touch bar shows as PNG
+
+Layers and buttons are counted from 0, named layers can also be given by
+name. Changes made here last until the config is reloaded.";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    process::exit(2)
+}
+
+fn index(arg: Option<String>) -> usize {
+    arg.and_then(|a| a.parse().ok()).unwrap_or_else(|| usage())
+}
+
+fn layer(arg: Option<String>) -> LayerRef {
+    let arg = arg.unwrap_or_else(|| usage());
+    match arg.parse() {
+        Ok(index) => LayerRef::Index(index),
+        Err(_) => LayerRef::Name(arg),
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> (Command, Option<String>) {
+    let command = match args.next().as_deref() {
+        Some("state") => Command::State,
+        Some("switch-layer") => Command::SwitchLayer {
+            layer: layer(args.next()),
+        },
+        Some("set-text") => Command::SetText {
+            layer: layer(args.next()),
+            button: index(args.next()),
+            text: args.next().unwrap_or_else(|| usage()),
+        },
+        Some("set-icon") => Command::SetIcon {
+            layer: layer(args.next()),
+            button: index(args.next()),
+            icon: args.next().unwrap_or_else(|| usage()),
+            theme: args.next(),
+        },
+        Some("fn-mode") => Command::SetFnMode {
+            mode: args.next().unwrap_or_else(|| usage()),
+            fn_layer_default: args.next().map(|layer| match layer.as_str() {
+                "primary" => false,
+                "fn" => true,
+                _ => usage(),
+            }),
+        },
+        Some("reload") => Command::Reload,
+        Some("screenshot") => {
+            return (
+                Command::Screenshot,
+                Some(args.next().unwrap_or_else(|| usage())),
+            );
+        }
+        _ => usage(),
+    };
+    if args.next().is_some() {
+        usage();
+    }
+    (command, None)
+}
+
+fn unhex(hex: &str) -> Result<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .ok_or_else(|| anyhow!("bad hex in reply"))
+        })
+        .collect()
+}
+
+fn run(command: Command, out: Option<String>) -> Result<()> {
+    let mut stream = UnixStream::connect(SOCKET_PATH)
+        .map_err(|err| anyhow!("Failed to connect to {SOCKET_PATH}: {err}"))?;
+    let request = Request {
+        version: PROTOCOL_VERSION,
+        command,
+    };
+    let mut line = serde_json::to_vec(&request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    let response: Response = serde_json::from_str(&reply)?;
+    if !response.ok {
+        return Err(anyhow!(
+            response
+                .error
+                .unwrap_or_else(|| "request failed".to_string())
+        ));
+    }
+    if let Some(state) = response.state {
+        println!("{}", serde_json::to_string_pretty(&state)?);
+    }
+    if let (Some(png), Some(out)) = (response.png, out) {
+        File::create(&out)?.write_all(&unhex(&png)?)?;
+        println!("Wrote {out}");
+    }
+    Ok(())
+}
+
+fn main() {
+    let (command, out) = parse_args(env::args().skip(1));
+    if let Err(err) = run(command, out) {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+}
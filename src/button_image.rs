@@ -1,6 +1,131 @@
-use cairo::ImageSurface;
-use librsvg_rebind::Handle;
+use crate::theme::Color;
+use cairo::{Context, ImageSurface, Operator};
+use librsvg_rebind::{Handle, Rectangle, prelude::HandleExt};
 pub enum ButtonImage {
     Svg(Handle),
     Bitmap(ImageSurface),
 }
+
+impl ButtonImage {
+    // Draw the image into a size x size square at (x, y)
+    fn render(&self, c: &Context, x: f64, y: f64, size: f64) {
+        match self {
+            ButtonImage::Svg(svg) => {
+                svg.render_document(c, &Rectangle::new(x, y, size, size))
+                    .unwrap();
+            }
+            ButtonImage::Bitmap(surf) => {
+                // Bitmaps are loaded at the icon size, so this only scales if asked for smaller
+                c.save().unwrap();
+                c.translate(x, y);
+                c.scale(size / surf.width() as f64, size / surf.height() as f64);
+                c.set_source_surface(surf, 0.0, 0.0).unwrap();
+                c.rectangle(0.0, 0.0, surf.width() as f64, surf.height() as f64);
+                c.fill().unwrap();
+                c.restore().unwrap();
+            }
+        }
+    }
+    // Fill the shape of the image with the current source.
+    // This is how symbolic icons are made to follow the text color.
+    fn render_recolored(&self, c: &Context, x: f64, y: f64, size: f64) {
+        c.push_group();
+        self.render(c, x, y, size);
+        let shape = c.pop_group().unwrap();
+        match self {
+            ButtonImage::Svg(_) => c.mask(&shape).unwrap(),
+            ButtonImage::Bitmap(_) => {
+                // Multiply instead of filling, so shading and colors survive the tint
+                let tint = c.source();
+                c.push_group();
+                c.set_source(&shape).unwrap();
+                c.paint().unwrap();
+                c.set_operator(Operator::Multiply);
+                c.set_source(&tint).unwrap();
+                c.mask(&shape).unwrap();
+                c.pop_group_to_source().unwrap();
+                c.paint().unwrap();
+            }
+        }
+    }
+    // Recolored images use color, or the current source when it is unset
+    pub fn draw(
+        &self,
+        c: &Context,
+        x: f64,
+        y: f64,
+        size: f64,
+        recolor: bool,
+        color: Option<Color>,
+    ) {
+        if !recolor {
+            self.render(c, x, y, size);
+            return;
+        }
+        c.save().unwrap();
+        if let Some(color) = color {
+            color.set_source(c);
+        }
+        self.render_recolored(c, x, y, size);
+        c.restore().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cairo::Format;
+
+    fn pixel(surf: &mut ImageSurface, x: usize) -> [u8; 4] {
+        surf.flush();
+        let data = surf.data().unwrap();
+        [
+            data[x * 4],
+            data[x * 4 + 1],
+            data[x * 4 + 2],
+            data[x * 4 + 3],
+        ]
+    }
+
+    #[test]
+    fn bitmap_tint_keeps_colors() {
+        // White on the left, blue on the right, transparent below
+        let icon = ImageSurface::create(Format::ARgb32, 2, 2).unwrap();
+        {
+            let c = Context::new(&icon).unwrap();
+            c.set_source_rgb(1.0, 1.0, 1.0);
+            c.rectangle(0.0, 0.0, 1.0, 1.0);
+            c.fill().unwrap();
+            c.set_source_rgb(0.0, 0.0, 1.0);
+            c.rectangle(1.0, 0.0, 1.0, 1.0);
+            c.fill().unwrap();
+        }
+        let image = ButtonImage::Bitmap(icon);
+        let mut out = ImageSurface::create(Format::ARgb32, 2, 2).unwrap();
+        {
+            let c = Context::new(&out).unwrap();
+            image.draw(&c, 0.0, 0.0, 2.0, true, Some(Color::rgb(1.0, 0.0, 1.0)));
+        }
+        // Pixels are BGRA in memory on little endian
+        assert_eq!(pixel(&mut out, 0), [255, 0, 255, 255]);
+        assert_eq!(pixel(&mut out, 1), [255, 0, 0, 255]);
+        assert_eq!(pixel(&mut out, 2)[3], 0);
+    }
+
+    #[test]
+    fn draws_as_is_without_recolor() {
+        let icon = ImageSurface::create(Format::ARgb32, 1, 1).unwrap();
+        {
+            let c = Context::new(&icon).unwrap();
+            c.set_source_rgb(0.0, 1.0, 0.0);
+            c.paint().unwrap();
+        }
+        let image = ButtonImage::Bitmap(icon);
+        let mut out = ImageSurface::create(Format::ARgb32, 1, 1).unwrap();
+        {
+            let c = Context::new(&out).unwrap();
+            image.draw(&c, 0.0, 0.0, 1.0, false, Some(Color::rgb(1.0, 0.0, 0.0)));
+        }
+        assert_eq!(pixel(&mut out, 0), [0, 255, 0, 255]);
+    }
+}
@@ -0,0 +1,455 @@
+use crate::{
+    config::load_command_user,
+    focus::{FocusWatcher, find_socket},
+};
+use anyhow::{Result, anyhow};
+use nix::{
+    sys::signal::{Signal, kill, killpg},
+    unistd::{ForkResult, Pid, User, fork, getgrouplist, getuid, setgid, setgroups, setuid},
+};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::CString,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd},
+        unix::{net::UnixStream, process::CommandExt},
+    },
+    path::PathBuf,
+    process::{self, Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How often to look for the compositor while it isn't running
+const FOCUS_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+const SAFE_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandRequest {
+    pub argv: Vec<String>,
+    pub timeout: Duration,
+}
+
+// Runs commands for the daemon. They are started by a helper process forked off
+// before privileges are dropped, as the user from the config and never as root.
+// The helper reads that user from the config files itself, and refuses any other.
+// Commands inherit the service's sandbox, see CommandUser in the config.
+// The helper also follows the focused app in that user's compositor, which the
+// daemon itself can't reach, through a child process running as the user.
+pub struct CommandRunner {
+    stream: UnixStream,
+    // What the helper sent that doesn't make a whole line yet
+    received: Vec<u8>,
+    user: Option<String>,
+    following_focus: Option<String>,
+    focused_app: String,
+    next_id: u64,
+    running: HashMap<u64, Vec<String>>,
+}
+
+impl CommandRunner {
+    // Must be called while still root and before any threads are started
+    pub fn spawn() -> Result<CommandRunner> {
+        let (daemon_end, helper_end) = UnixStream::pair()?;
+        match unsafe { fork() }? {
+            ForkResult::Child => {
+                drop(daemon_end);
+                close_other_fds(helper_end.as_raw_fd());
+                serve(helper_end);
+                process::exit(0);
+            }
+            ForkResult::Parent { .. } => {
+                drop(helper_end);
+                daemon_end.set_nonblocking(true)?;
+                Ok(CommandRunner {
+                    stream: daemon_end,
+                    received: Vec::new(),
+                    user: None,
+                    following_focus: None,
+                    focused_app: String::new(),
+                    next_id: 0,
+                    running: HashMap::new(),
+                })
+            }
+        }
+    }
+    pub fn set_user(&mut self, user: Option<String>, follow_focus: bool) {
+        let following = user.clone().filter(|_| follow_focus);
+        if following != self.following_focus {
+            self.send(&["focus", following.as_deref().unwrap_or_default()]);
+            self.focused_app.clear();
+            self.following_focus = following;
+        }
+        self.user = user;
+    }
+    // The app focused in the compositor of the user, empty if unknown
+    pub fn focused_app(&self) -> &str {
+        &self.focused_app
+    }
+    // NUL separated fields, ended by an empty one
+    fn send(&mut self, fields: &[&str]) -> bool {
+        let mut msg = Vec::new();
+        for field in fields {
+            msg.extend_from_slice(field.as_bytes());
+            msg.push(0);
+        }
+        msg.push(0);
+        match self.stream.write_all(&msg) {
+            Ok(()) => true,
+            Err(err) => {
+                println!("Failed to reach the command helper: {err}");
+                false
+            }
+        }
+    }
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.stream.as_fd()
+    }
+    pub fn run(&mut self, request: &CommandRequest) {
+        let Some(user) = self.user.clone() else {
+            println!("Not running {:?}, CommandUser is not set", request.argv);
+            return;
+        };
+        let timeout = request.timeout.as_millis().to_string();
+        let id = self.next_id.to_string();
+        let mut fields = vec!["run", &id, &user, &timeout];
+        fields.extend(request.argv.iter().map(String::as_str));
+        if !self.send(&fields) {
+            return;
+        }
+        self.running.insert(self.next_id, request.argv.clone());
+        self.next_id += 1;
+    }
+    // Commands that finished since the last call, and whether they succeeded.
+    // Also picks up focus changes.
+    pub fn finished(&mut self) -> Vec<(Vec<String>, bool)> {
+        let mut finished = Vec::new();
+        let mut chunk = [0; 4096];
+        // Nonblocking, this stops once everything sent so far is read
+        while let Ok(n) = self.stream.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            self.received.extend_from_slice(&chunk[..n]);
+        }
+        // The rest of a line cut short arrives with a later wakeup
+        while let Some(end) = self.received.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.received.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]);
+            if let Some(app) = line.strip_prefix("focus ") {
+                self.focused_app = app.to_string();
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            if let (Some(id), Some(status)) = (fields.next(), fields.next())
+                && let Ok(id) = id.parse::<u64>()
+                && let Some(argv) = self.running.remove(&id)
+            {
+                finished.push((argv, status == "ok"));
+            }
+        }
+        finished
+    }
+}
+
+// The helper shouldn't hold on to the display or input devices
+fn close_other_fds(keep: i32) {
+    let Ok(entries) = fs::read_dir("/proc/self/fd") else {
+        return;
+    };
+    let fds: Vec<i32> = entries
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+        .filter(|&fd| fd > 2 && fd != keep)
+        .collect();
+    for fd in fds {
+        unsafe { libc::close(fd) };
+    }
+}
+
+// Who the focus watcher follows, and the child process talking to their compositor
+#[derive(Default)]
+struct FocusTarget {
+    user: Option<String>,
+    child: Option<Pid>,
+}
+
+fn serve(stream: UnixStream) {
+    let results = Arc::new(Mutex::new(stream.try_clone().unwrap()));
+    let focus_target = Arc::new(Mutex::new(FocusTarget::default()));
+    {
+        let (target, results) = (focus_target.clone(), results.clone());
+        thread::spawn(move || watch_focus(&target, &results));
+    }
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut fields = Vec::new();
+        loop {
+            let mut field = Vec::new();
+            // The daemon went away
+            if reader.read_until(0, &mut field).unwrap_or(0) == 0 {
+                return;
+            }
+            field.pop();
+            if field.is_empty() {
+                break;
+            }
+            fields.push(String::from_utf8_lossy(&field).into_owned());
+        }
+        if let [kind, user] = fields.as_slice()
+            && kind == "focus"
+        {
+            let mut user = Some(user.clone()).filter(|u| !u.is_empty());
+            if let Some(name) = &user
+                && let Err(err) = check_user(name)
+            {
+                println!("Not following focus: {err}");
+                user = None;
+            }
+            let mut target = focus_target.lock().unwrap();
+            target.user = user;
+            // Wakes up the watcher, so it follows the new user
+            if let Some(child) = target.child.take() {
+                let _ = kill(child, Signal::SIGKILL);
+            }
+            continue;
+        }
+        let results = results.clone();
+        thread::spawn(move || {
+            let [kind, id, user, timeout, argv @ ..] = fields.as_slice() else {
+                return;
+            };
+            if kind != "run" {
+                return;
+            }
+            let timeout = Duration::from_millis(timeout.parse().unwrap_or(0));
+            let ok = match check_user(user).and_then(|()| run_as(user, argv, timeout)) {
+                Ok(()) => true,
+                Err(err) => {
+                    println!("Command {argv:?} failed: {err}");
+                    false
+                }
+            };
+            let status = if ok { "ok" } else { "failed" };
+            // A single write, so the line never reaches the daemon in pieces
+            let line = format!("{id} {status}\n");
+            let _ = results.lock().unwrap().write_all(line.as_bytes());
+        });
+    }
+}
+
+fn watch_focus(target: &Mutex<FocusTarget>, results: &Mutex<UnixStream>) {
+    // Framed like the results, one line in one write
+    let report = |app: &str| {
+        let line = format!("focus {app}\n");
+        let _ = results.lock().unwrap().write_all(line.as_bytes());
+    };
+    let mut last_error = String::new();
+    loop {
+        let Some(user) = target.lock().unwrap().user.clone() else {
+            thread::sleep(FOCUS_RETRY_INTERVAL);
+            continue;
+        };
+        let mut child = None;
+        let followed = (|| -> Result<()> {
+            let watcher = child.insert(spawn_focus_child(&user)?);
+            let stdout = watcher.stdout.take().unwrap();
+            {
+                let mut target = target.lock().unwrap();
+                if target.user.as_deref() != Some(&user) {
+                    return Ok(());
+                }
+                target.child = Some(Pid::from_raw(watcher.id() as i32));
+            }
+            for line in BufReader::new(stdout).lines() {
+                let line = line?;
+                if let Some(app) = line.strip_prefix("focus ") {
+                    report(app);
+                } else if let Some(socket) = line.strip_prefix("following ") {
+                    println!("Following focus in {socket}");
+                    last_error.clear();
+                } else if let Some(err) = line.strip_prefix("error ") {
+                    return Err(anyhow!("{err}"));
+                }
+            }
+            Err(anyhow!("the focus watcher for {user} exited"))
+        })();
+        let still_wanted = {
+            let mut target = target.lock().unwrap();
+            target.child = None;
+            target.user.as_deref() == Some(&user)
+        };
+        if let Some(mut child) = child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if let Err(err) = followed
+            && still_wanted
+        {
+            let err = err.to_string();
+            // Only once, this keeps failing while nobody is logged in
+            if err != last_error {
+                println!("Not following focus: {err}");
+                last_error = err;
+            }
+            report("");
+        }
+        thread::sleep(FOCUS_RETRY_INTERVAL);
+    }
+}
+
+// The compositor's sockets belong to the user, so they are only ever touched by
+// a process that has become that user. The helper has threads by now, so rather
+// than forking it, the watcher is a fresh tiny-dfr --follow-focus reporting back
+// in lines on its stdout.
+fn spawn_focus_child(name: &str) -> Result<Child> {
+    let user = User::from_name(name)?.ok_or_else(|| anyhow!("no user named {name}"))?;
+    if user.uid.is_root() {
+        return Err(anyhow!("refusing to follow focus as root"));
+    }
+    let (uid, gid) = (user.uid, user.gid);
+    // Before spawning, like in run_as
+    let groups = getgrouplist(&CString::new(name)?, gid)?;
+    let mut command = Command::new(env::current_exe()?);
+    command
+        .arg("--follow-focus")
+        .env_clear()
+        .current_dir("/")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+    unsafe {
+        command.pre_exec(move || {
+            setgroups(&groups)?;
+            setgid(gid)?;
+            setuid(uid)?;
+            Ok(())
+        });
+    }
+    Ok(command.spawn()?)
+}
+
+// Runs in the process started by spawn_focus_child
+pub fn run_focus_watcher() {
+    let mut out = io::stdout().lock();
+    if let Err(err) = follow_focus(&mut out) {
+        let line = format!("error {}\n", err.to_string().replace('\n', " "));
+        let _ = out.write_all(line.as_bytes());
+    }
+}
+
+fn follow_focus(out: &mut impl Write) -> Result<()> {
+    let uid = getuid();
+    let user = User::from_uid(uid)?.ok_or_else(|| anyhow!("no user with uid {uid}"))?;
+    let runtime_dir = PathBuf::from(format!("/run/user/{uid}"));
+    let (compositor, path) = find_socket(&runtime_dir)
+        .ok_or_else(|| anyhow!("no Sway or Hyprland session for {}", user.name))?;
+    let (mut watcher, mut app) = FocusWatcher::connect(compositor, &path)?;
+    let line = format!("following {compositor:?} at {}\n", path.display());
+    out.write_all(line.as_bytes())?;
+    loop {
+        // Names are sent as a line
+        let line = format!("focus {}\n", app.replace('\n', " "));
+        out.write_all(line.as_bytes())?;
+        app = watcher.next_focus()?;
+    }
+}
+
+// Only the CommandUser of the root owned config is accepted
+fn check_user(name: &str) -> Result<()> {
+    if load_command_user().as_deref() != Some(name) {
+        return Err(anyhow!("{name} is not the CommandUser in the config"));
+    }
+    Ok(())
+}
+
+fn run_as(name: &str, argv: &[String], timeout: Duration) -> Result<()> {
+    let user = User::from_name(name)?.ok_or_else(|| anyhow!("no user named {name}"))?;
+    if user.uid.is_root() {
+        return Err(anyhow!("refusing to run commands as root"));
+    }
+    let (program, args) = argv.split_first().ok_or_else(|| anyhow!("empty command"))?;
+    let (uid, gid) = (user.uid, user.gid);
+    // Looked up here, only async signal safe calls may happen between fork and exec
+    let groups = getgrouplist(&CString::new(name)?, gid)?;
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .env_clear()
+        .env("HOME", &user.dir)
+        .env("USER", name)
+        .env("LOGNAME", name)
+        .env("SHELL", &user.shell)
+        .env("PATH", SAFE_PATH)
+        .env("XDG_RUNTIME_DIR", format!("/run/user/{uid}"))
+        .env(
+            "DBUS_SESSION_BUS_ADDRESS",
+            format!("unix:path=/run/user/{uid}/bus"),
+        )
+        .current_dir(&user.dir)
+        .stdin(Stdio::null())
+        // Its own process group, so a timeout gets rid of anything it started too
+        .process_group(0);
+    unsafe {
+        command.pre_exec(move || {
+            setgroups(&groups)?;
+            setgid(gid)?;
+            setuid(uid)?;
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            println!("Command {argv:?} exited with {status}");
+            return if status.success() {
+                Ok(())
+            } else {
+                Err(anyhow!("{status}"))
+            };
+        }
+        if Instant::now() >= deadline {
+            let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+            child.wait()?;
+            return Err(anyhow!("timed out after {}s", timeout.as_secs_f64()));
+        }
+        thread::sleep(WAIT_POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_lines_cut_short() {
+        let (daemon_end, mut helper_end) = UnixStream::pair().unwrap();
+        daemon_end.set_nonblocking(true).unwrap();
+        let mut runner = CommandRunner {
+            stream: daemon_end,
+            received: Vec::new(),
+            user: None,
+            following_focus: None,
+            focused_app: String::new(),
+            next_id: 2,
+            running: HashMap::from([
+                (0, vec!["true".to_string()]),
+                (1, vec!["false".to_string()]),
+            ]),
+        };
+        helper_end.write_all(b"0 ok\nfocus fire").unwrap();
+        assert_eq!(runner.finished(), [(vec!["true".to_string()], true)]);
+        assert_eq!(runner.focused_app(), "");
+        helper_end.write_all(b"fox\n1 fail").unwrap();
+        assert_eq!(runner.finished(), []);
+        assert_eq!(runner.focused_app(), "firefox");
+        helper_end.write_all(b"ed\n").unwrap();
+        assert_eq!(runner.finished(), [(vec!["false".to_string()], false)]);
+        assert!(runner.received.is_empty());
+    }
+}
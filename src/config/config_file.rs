@@ -1,18 +1,52 @@
 use crate::{
-    fonts::{FontConfig, Pattern},
+    action::Action,
+    animation::{AnimationConfig, TransitionKind},
+    display::Orientation,
+    fn_key::FnMode,
     function_layer::FunctionLayer,
+    gestures::GestureBindings,
+    layout::{LayoutMetrics, LayoutOverrides},
+    modifiers::Modifier,
+    text::FontCache,
+    theme::{Theme, ThemeConfig},
 };
-use anyhow::Error;
-use cairo::FontFace;
-use freetype::Library as FtLibrary;
 use input_linux::Key;
+use pangocairo::pango::FontDescription;
 use serde::Deserialize;
-use std::fs::read_to_string;
+use std::{collections::HashMap, fs::read_to_string, time::Duration};
 
-use super::{USER_CFG_PATH, config_struct::Config, widget::ButtonConfig};
+use super::{
+    USER_CFG_PATH,
+    config_struct::{AppLayer, Config, DisplayConfig, ModifierLayer},
+    widget::ButtonConfig,
+};
+
+// Buttons shown instead of the primary layer while a matching app has focus
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+struct AppLayerProxy {
+    app: String,
+    keys: Vec<ButtonConfig>,
+}
 
+// A layer that buttons can push, pop or switch to by name
 #[derive(Deserialize)]
-#[serde(rename_all = "PascalCase")]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+struct NamedLayerProxy {
+    name: String,
+    keys: Vec<ButtonConfig>,
+}
+
+// A named layer shown while exactly these modifiers are held
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+struct ModifierLayerProxy {
+    modifiers: Vec<Modifier>,
+    layer: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
 struct ConfigProxy {
     show_button_outlines: Option<bool>,
     enable_pixel_shift: Option<bool>,
@@ -22,18 +56,45 @@ struct ConfigProxy {
     active_brightness: Option<u32>,
     dim_brightness: Option<u32>,
     off_brightness: Option<u32>,
+    rotation: Option<u16>,
+    flip_horizontal: Option<bool>,
+    flip_vertical: Option<bool>,
+    min_aspect_ratio: Option<u16>,
+    layout: Option<LayoutOverrides>,
+    theme: Option<ThemeConfig>,
+    layer_transition: Option<TransitionKind>,
+    animation_duration: Option<u64>,
+    press_ripple: Option<bool>,
+    gestures: Option<GestureBindings>,
+    command_user: Option<String>,
+    fn_mode: Option<FnMode>,
+    fn_layer_default: Option<bool>,
     primary_layer_keys: Option<Vec<ButtonConfig>>,
     fn_layer_keys: Option<Vec<ButtonConfig>>,
+    app_layers: Option<Vec<AppLayerProxy>>,
+    layers: Option<Vec<NamedLayerProxy>>,
+    modifier_layers: Option<Vec<ModifierLayerProxy>>,
 }
 
-pub fn load_config(width: u16) -> (Config, [FunctionLayer; 2]) {
-    let mut base =
-        toml::from_str::<ConfigProxy>(&read_to_string("/usr/share/tiny-dfr/config.toml").unwrap())
-            .unwrap();
-    let user = read_to_string(USER_CFG_PATH)
-        .map_err::<Error, _>(|e| e.into())
-        .and_then(|r| Ok(toml::from_str::<ConfigProxy>(&r)?));
-    if let Ok(user) = user {
+pub fn read_user_config() -> Option<String> {
+    read_to_string(USER_CFG_PATH).ok()
+}
+
+const BASE_CFG_PATH: &str = "/usr/share/tiny-dfr/config.toml";
+
+fn read_config(user: Option<&str>) -> ConfigProxy {
+    parse_config(&read_to_string(BASE_CFG_PATH).unwrap(), user)
+}
+
+// Merge the user config, if any, on top of the packaged one
+fn parse_config(base: &str, user: Option<&str>) -> ConfigProxy {
+    let mut base = toml::from_str::<ConfigProxy>(base)
+        .unwrap_or_else(|e| panic!("config error in {BASE_CFG_PATH}: {}", e.message()));
+    let user = user.map(|user| {
+        toml::from_str::<ConfigProxy>(user)
+            .unwrap_or_else(|e| panic!("config error in {USER_CFG_PATH}: {}", e.message()))
+    });
+    if let Some(user) = user {
         base.show_button_outlines = user.show_button_outlines.or(base.show_button_outlines);
         base.enable_pixel_shift = user.enable_pixel_shift.or(base.enable_pixel_shift);
         base.font_template = user.font_template.or(base.font_template);
@@ -43,17 +104,121 @@ pub fn load_config(width: u16) -> (Config, [FunctionLayer; 2]) {
         base.active_brightness = user.active_brightness.or(base.active_brightness);
         base.dim_brightness = user.dim_brightness.or(base.dim_brightness);
         base.off_brightness = user.off_brightness.or(base.off_brightness);
+        base.rotation = user.rotation.or(base.rotation);
+        base.flip_horizontal = user.flip_horizontal.or(base.flip_horizontal);
+        base.flip_vertical = user.flip_vertical.or(base.flip_vertical);
+        base.min_aspect_ratio = user.min_aspect_ratio.or(base.min_aspect_ratio);
+        base.layout = user.layout.or(base.layout);
+        base.theme = user.theme.or(base.theme);
+        base.layer_transition = user.layer_transition.or(base.layer_transition);
+        base.animation_duration = user.animation_duration.or(base.animation_duration);
+        base.press_ripple = user.press_ripple.or(base.press_ripple);
+        base.gestures = user.gestures.or(base.gestures);
+        base.command_user = user.command_user.or(base.command_user);
+        base.app_layers = user.app_layers.or(base.app_layers);
+        base.layers = user.layers.or(base.layers);
+        base.modifier_layers = user.modifier_layers.or(base.modifier_layers);
+        base.fn_mode = user.fn_mode.or(base.fn_mode);
+        base.fn_layer_default = user.fn_layer_default.or(base.fn_layer_default);
     };
+    base
+}
+
+fn orientation(base: &ConfigProxy) -> Orientation {
+    Orientation::new(
+        base.rotation.unwrap(),
+        base.flip_horizontal.unwrap(),
+        base.flip_vertical.unwrap(),
+    )
+}
+
+// Settings needed before the display can be opened
+pub fn load_display_config() -> DisplayConfig {
+    let base = read_config(read_user_config().as_deref());
+    DisplayConfig {
+        orientation: orientation(&base),
+        min_aspect_ratio: base.min_aspect_ratio.unwrap(),
+    }
+}
+
+pub fn load_config(mode_size: (u16, u16), user: Option<&str>) -> (Config, Vec<FunctionLayer>) {
+    load_config_from(&read_to_string(BASE_CFG_PATH).unwrap(), mode_size, user)
+}
+
+// Like load_config, with the packaged config passed in rather than read from disk
+pub fn load_config_from(
+    base: &str,
+    mode_size: (u16, u16),
+    user: Option<&str>,
+) -> (Config, Vec<FunctionLayer>) {
+    let base = parse_config(base, user);
+    let orientation = orientation(&base);
+    let (width, height) = orientation.logical_size(mode_size);
+    let layout = LayoutMetrics::new(height, &base.layout.unwrap_or_default());
+    let theme = Theme::new(layout.corner_radius).with_overrides(&base.theme.unwrap_or_default());
+    let mut fonts = FontCache::new(&base.font_template.unwrap(), layout.font_size);
     let mut media_layer_keys = base.fn_layer_keys.unwrap();
     let mut primary_layer_keys = base.primary_layer_keys.unwrap();
+    let mut app_layers = base.app_layers.unwrap_or_default();
+    let mut named_layers = base.layers.unwrap_or_default();
+    let gestures = base.gestures.unwrap_or_default();
+    // Named layers go after the primary, Fn and app layers
+    let mut layer_indices = HashMap::from([("Primary".to_string(), 0), ("Fn".to_string(), 1)]);
+    for (i, named) in named_layers.iter().enumerate() {
+        let index = 2 + app_layers.len() + i;
+        if layer_indices.insert(named.name.clone(), index).is_some() {
+            panic!(
+                "Invalid configuration, there is more than one layer called {}",
+                named.name
+            );
+        }
+    }
+    // Layers are referred to by name, so typos are caught here
+    let all_buttons = [&primary_layer_keys, &media_layer_keys]
+        .into_iter()
+        .chain(app_layers.iter().map(|a| &a.keys))
+        .chain(named_layers.iter().map(|n| &n.keys))
+        .flatten();
+    let button_actions = all_buttons.flat_map(|b| [Some(&b.action), b.secondary_action.as_ref()]);
+    for action in button_actions.flatten().chain(gestures.actions()) {
+        if let Action::Layer(action) = action
+            && let Some(name) = action.layer_name()
+            && !layer_indices.contains_key(name)
+        {
+            panic!("Invalid configuration, there is no layer called {name}");
+        }
+    }
+    let modifier_layers = base
+        .modifier_layers
+        .unwrap_or_default()
+        .into_iter()
+        .map(|m| {
+            let Some(&layer) = layer_indices.get(&m.layer) else {
+                panic!(
+                    "Invalid configuration, there is no layer called {}",
+                    m.layer
+                );
+            };
+            let mut modifiers = m.modifiers;
+            modifiers.sort();
+            modifiers.dedup();
+            ModifierLayer { modifiers, layer }
+        })
+        .collect();
     // If the device doesn't have a physical Esc key, inject a soft one
     if width >= 2170 {
-        for layer in [&mut media_layer_keys, &mut primary_layer_keys] {
+        let app_layer_keys = app_layers.iter_mut().map(|a| &mut a.keys);
+        let named_layer_keys = named_layers.iter_mut().map(|n| &mut n.keys);
+        for layer in [&mut media_layer_keys, &mut primary_layer_keys]
+            .into_iter()
+            .chain(app_layer_keys)
+            .chain(named_layer_keys)
+        {
             layer.insert(
                 0,
                 ButtonConfig {
                     text: Some("esc".into()),
-                    action: Key::Esc,
+                    action: Action::Key(Key::Esc),
                     icon: None,
                     theme: None,
                     stretch: None,
@@ -62,38 +227,122 @@ pub fn load_config(width: u16) -> (Config, [FunctionLayer; 2]) {
                     battery: None,
                     processor: None,
                     memory: None,
+                    style: None,
+                    wrap: None,
+                    font: None,
+                    font_size: None,
+                    icon_position: None,
+                    align: None,
+                    icon_spacing: None,
+                    recolor: None,
+                    graph: None,
+                    graph_style: None,
+                    history: None,
+                    sample_interval: None,
+                    slider: None,
+                    secondary_action: None,
+                    repeat: None,
                 },
             );
         }
     }
-    let fn_layer = FunctionLayer::with_config(media_layer_keys);
-    let primary_layer = FunctionLayer::with_config(primary_layer_keys);
+    let fn_layer = FunctionLayer::with_config(media_layer_keys, &layout, &theme, &mut fonts);
+    let primary_layer = FunctionLayer::with_config(primary_layer_keys, &layout, &theme, &mut fonts);
+    let mut layers = vec![primary_layer, fn_layer];
+    let app_layers = app_layers
+        .into_iter()
+        .map(|app_layer| {
+            layers.push(FunctionLayer::with_config(
+                app_layer.keys,
+                &layout,
+                &theme,
+                &mut fonts,
+            ));
+            AppLayer {
+                app: app_layer.app,
+                layer: layers.len() - 1,
+            }
+        })
+        .collect();
+    for named in named_layers {
+        layers.push(FunctionLayer::with_config(
+            named.keys, &layout, &theme, &mut fonts,
+        ));
+    }
 
     let cfg = Config {
         show_button_outlines: base.show_button_outlines.unwrap(),
         enable_pixel_shift: base.enable_pixel_shift.unwrap(),
         adaptive_brightness: base.adaptive_brightness.unwrap(),
-        font_face: load_font(&base.font_template.unwrap()),
         active_brightness: base.active_brightness.unwrap(),
         dim_brightness: base.dim_brightness.unwrap(),
         off_brightness: base.off_brightness.unwrap(),
+        orientation,
+        layout,
+        theme,
+        animation: AnimationConfig {
+            duration: Duration::from_millis(base.animation_duration.unwrap()),
+            layer_transition: base.layer_transition.unwrap(),
+            press_ripple: base.press_ripple.unwrap(),
+        },
+        gestures,
+        command_user: base.command_user,
+        app_layers,
+        layer_indices,
+        modifier_layers,
+        fn_mode: base.fn_mode.unwrap(),
+        fn_layer_default: base.fn_layer_default.unwrap(),
     };
-    (cfg, [primary_layer, fn_layer])
+    (cfg, layers)
 }
 
-fn load_font(name: &str) -> FontFace {
-    let fontconfig = FontConfig::new();
-    let mut pattern = Pattern::new(name);
-    fontconfig.perform_substitutions(&mut pattern);
-    let pat_match = match fontconfig.match_pattern(&pattern) {
-        Ok(pat) => pat,
-        Err(_) => panic!(
-            "Unable to find specified font. If you are using the default config, make sure you have at least one font installed"
-        ),
+// CommandUser straight from the files, for the command helper, which shouldn't take
+// the daemon's word for who to run commands as. None if the config can't be read.
+pub fn load_command_user() -> Option<String> {
+    let base = toml::from_str::<ConfigProxy>(&read_to_string(BASE_CFG_PATH).ok()?).ok()?;
+    let user = match read_user_config() {
+        Some(user) => Some(toml::from_str::<ConfigProxy>(&user).ok()?),
+        None => None,
     };
-    let file_name = pat_match.get_file_name();
-    let file_idx = pat_match.get_font_index();
-    let ft_library = FtLibrary::init().unwrap();
-    let face = ft_library.new_face(file_name, file_idx).unwrap();
-    FontFace::create_from_ft(&face).unwrap()
+    user.and_then(|user| user.command_user)
+        .or(base.command_user)
+}
+
+// Font for the crash screen, this panics if the config itself is broken
+pub fn load_font_description() -> FontDescription {
+    let base = read_config(read_user_config().as_deref());
+    FontCache::new(&base.font_template.unwrap(), 0.0)
+        .get(None, None)
+        .as_ref()
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKAGED: &str = include_str!("../../share/tiny-dfr/config.toml");
+
+    fn error(user: &str) -> String {
+        match toml::from_str::<ConfigProxy>(user) {
+            Ok(_) => panic!("{user} parsed"),
+            Err(e) => e.message().to_string(),
+        }
+    }
+
+    #[test]
+    fn packaged_config_parses() {
+        toml::from_str::<ConfigProxy>(PACKAGED).unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        assert!(error("Foo = 1").contains("unknown field `Foo`"));
+        assert!(
+            error("FnLayerKeys = [{ Text = \"F1\", Action = \"F1\", Foo = 1 }]").contains("Foo")
+        );
+        assert!(error("[Theme]\nTextColor = \"#ffffff\"").contains("TextColor"));
+        assert!(error("[Layout]\nSpacing = 4").contains("Spacing"));
+        assert!(error("[Gestures]\nSwipeUp = \"SwitchLayer\"").contains("SwipeUp"));
+    }
 }
@@ -1,11 +1,81 @@
-use cairo::FontFace;
+use crate::{
+    animation::AnimationConfig, display::Orientation, fn_key::FnMode, focus::app_matches,
+    gestures::GestureBindings, layout::LayoutMetrics, modifiers::Modifier, theme::Theme,
+};
+use std::collections::HashMap;
+
+pub struct ModifierLayer {
+    // Sorted, like ModifierTracker::held
+    pub modifiers: Vec<Modifier>,
+    pub layer: usize,
+}
+
+pub struct AppLayer {
+    // Matched against the app_id or X11 class of the focused window
+    pub app: String,
+    pub layer: usize,
+}
 
 pub struct Config {
     pub show_button_outlines: bool,
     pub enable_pixel_shift: bool,
-    pub font_face: FontFace,
     pub adaptive_brightness: bool,
     pub active_brightness: u32,
     pub dim_brightness: u32,
     pub off_brightness: u32,
+    pub orientation: Orientation,
+    pub layout: LayoutMetrics,
+    pub theme: Theme,
+    pub animation: AnimationConfig,
+    pub gestures: GestureBindings,
+    // Who Command actions run as
+    pub command_user: Option<String>,
+    // Layers that replace the primary one while their app has focus
+    pub app_layers: Vec<AppLayer>,
+    // Names buttons use to push, pop and switch layers
+    pub layer_indices: HashMap<String, usize>,
+    pub modifier_layers: Vec<ModifierLayer>,
+    pub fn_mode: FnMode,
+    pub fn_layer_default: bool,
+}
+
+impl Config {
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.layer_indices.get(name).copied()
+    }
+    // The layer for exactly the held modifiers, if there is one
+    pub fn modifier_layer(&self, held: &[Modifier]) -> Option<usize> {
+        if held.is_empty() {
+            return None;
+        }
+        self.modifier_layers
+            .iter()
+            .find(|m| m.modifiers == held)
+            .map(|m| m.layer)
+    }
+    // The layer shown while Fn isn't held, for the focused app
+    pub fn base_layer(&self, focused_app: &str) -> usize {
+        if focused_app.is_empty() {
+            return 0;
+        }
+        self.app_layers
+            .iter()
+            .find(|a| app_matches(&a.app, focused_app))
+            .map_or(0, |a| a.layer)
+    }
+}
+
+pub struct DisplayConfig {
+    pub orientation: Orientation,
+    pub min_aspect_ratio: u16,
+}
+
+// Used to get the crash screen up when the config can't be read
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            orientation: Orientation::default(),
+            min_aspect_ratio: 30,
+        }
+    }
 }
@@ -5,11 +5,18 @@ use nix::{
 };
 use std::os::fd::AsFd;
 
-use super::{USER_CFG_PATH, config_file::load_config, config_struct::Config};
+use super::{
+    USER_CFG_PATH,
+    config_file::{load_config, read_user_config},
+    config_struct::Config,
+};
 
 pub struct ConfigManager {
     inotify_fd: Inotify,
     watch_desc: Option<WatchDescriptor>,
+    // User config that last loaded without errors, None if none has yet
+    last_good: Option<String>,
+    use_last_good: bool,
 }
 
 fn arm_inotify(inotify_fd: &Inotify) -> Option<WatchDescriptor> {
@@ -28,16 +35,47 @@ impl ConfigManager {
         ConfigManager {
             inotify_fd,
             watch_desc,
+            last_good: None,
+            use_last_good: false,
+        }
+    }
+    pub fn load_config(&mut self, mode_size: (u16, u16)) -> (Config, Vec<FunctionLayer>) {
+        if self.use_last_good {
+            return load_config(mode_size, self.last_good.as_deref());
+        }
+        let user = read_user_config();
+        // Panics if the user config doesn't parse or validate, so it is only kept once it did
+        let parts = load_config(mode_size, user.as_deref());
+        self.last_good = user;
+        parts
+    }
+    // Stick to the last config that loaded until the user config changes again,
+    // or to the packaged one if the user config never loaded
+    pub fn fall_back(&mut self) {
+        if self.use_last_good {
+            return;
         }
+        match self.last_good {
+            Some(_) => println!("Falling back to the last working config"),
+            None => println!("Falling back to the packaged config"),
+        }
+        self.use_last_good = true;
     }
-    pub fn load_config(&self, width: u16) -> (Config, [FunctionLayer; 2]) {
-        load_config(width)
+    // Load the user config again without waiting for it to change
+    pub fn reload(
+        &mut self,
+        cfg: &mut Config,
+        layers: &mut Vec<FunctionLayer>,
+        mode_size: (u16, u16),
+    ) {
+        self.use_last_good = false;
+        (*cfg, *layers) = self.load_config(mode_size);
     }
     pub fn update_config(
         &mut self,
         cfg: &mut Config,
-        layers: &mut [FunctionLayer; 2],
-        width: u16,
+        layers: &mut Vec<FunctionLayer>,
+        mode_size: (u16, u16),
     ) -> bool {
         if self.watch_desc.is_none() {
             self.watch_desc = arm_inotify(&self.inotify_fd);
@@ -50,14 +88,16 @@ impl ConfigManager {
         };
         let mut ret = false;
         for evt in evts {
-            if evt.wd != self.watch_desc.unwrap() {
+            if Some(evt.wd) != self.watch_desc {
                 continue;
             }
-            let parts = load_config(width);
+            // Re-arm first, a broken config must not stop us from seeing the fix
+            self.watch_desc = arm_inotify(&self.inotify_fd);
+            self.use_last_good = false;
+            let parts = self.load_config(mode_size);
             *cfg = parts.0;
             *layers = parts.1;
             ret = true;
-            self.watch_desc = arm_inotify(&self.inotify_fd);
         }
         ret
     }
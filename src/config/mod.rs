@@ -5,6 +5,9 @@ mod widget;
 
 const USER_CFG_PATH: &str = "/etc/tiny-dfr/config.toml";
 
-pub use self::config_struct::Config;
+#[cfg(test)]
+pub use self::config_file::load_config_from;
+pub use self::config_file::{load_command_user, load_display_config, load_font_description};
+pub use self::config_struct::{Config, DisplayConfig};
 pub use self::manager::*;
 pub use self::widget::*;
@@ -1,8 +1,13 @@
+use crate::{
+    action::{Action, RepeatConfig},
+    theme::ThemeConfig,
+    widgets::{Align, GraphSource, GraphStyle, IconPosition, SliderSource},
+};
 use input_linux::Key;
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug)]
-#[serde(rename_all = "PascalCase")]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
 pub struct ButtonConfig {
     #[serde(alias = "Svg")]
     pub icon: Option<String>,
@@ -13,6 +18,30 @@ pub struct ButtonConfig {
     pub memory: Option<String>,
     pub battery: Option<String>,
     pub locale: Option<String>,
-    pub action: Key,
+    // Sliders don't send their own key, so they can leave it out
+    #[serde(default = "no_action")]
+    pub action: Action,
     pub stretch: Option<usize>,
+    pub style: Option<ThemeConfig>,
+    pub wrap: Option<bool>,
+    pub font: Option<String>,
+    pub font_size: Option<f64>,
+    pub icon_position: Option<IconPosition>,
+    pub align: Option<Align>,
+    pub icon_spacing: Option<f64>,
+    pub recolor: Option<bool>,
+    pub graph: Option<GraphSource>,
+    pub graph_style: Option<GraphStyle>,
+    // Number of samples shown by a graph
+    pub history: Option<usize>,
+    // Time between graph samples in milliseconds
+    pub sample_interval: Option<u64>,
+    pub slider: Option<SliderSource>,
+    // Sent instead of Action by the LongPress and DoubleTap gestures
+    pub secondary_action: Option<Action>,
+    pub repeat: Option<RepeatConfig>,
+}
+
+fn no_action() -> Action {
+    Action::Key(Key::Reserved)
 }
@@ -1,7 +1,3 @@
 use std::time::Duration;
 
-pub const BUTTON_SPACING_PX: i32 = 16;
-pub const BUTTON_COLOR_INACTIVE: f64 = 0.200;
-pub const BUTTON_COLOR_ACTIVE: f64 = 0.400;
-pub const ICON_SIZE: i32 = 48;
 pub const TIMEOUT_MS: Duration = Duration::from_secs(10);
@@ -0,0 +1,198 @@
+mod protocol;
+
+use crate::config::Config;
+use anyhow::Result;
+use nix::sys::epoll::{Epoll, EpollEvent, EpollFlags};
+use std::{
+    fmt, fs,
+    io::{ErrorKind, Read, Write},
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::Path,
+    time::Duration,
+};
+
+pub use self::protocol::*;
+
+// Epoll token shared by all connected clients, they are all read when it fires
+pub const CLIENT_TOKEN: u64 = 7;
+// Longest a client gets to take a reply before it is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+// Requests longer than this are garbage
+const MAX_LINE: usize = 64 * 1024;
+
+struct Client {
+    id: u64,
+    stream: UnixStream,
+    buf: Vec<u8>,
+}
+
+// Listens on SOCKET_PATH for JSON line requests from tiny-dfr-ctl and scripts.
+// The socket is only accessible to root.
+pub struct ControlServer {
+    listener: UnixListener,
+    clients: Vec<Client>,
+    next_id: u64,
+}
+
+impl ControlServer {
+    // Must be called while still root
+    pub fn bind() -> Result<ControlServer> {
+        let path = Path::new(SOCKET_PATH);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Left behind by a previous instance
+        let _ = fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+        Ok(ControlServer {
+            listener,
+            clients: Vec::new(),
+            next_id: 0,
+        })
+    }
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+    // Clients are registered with the epoll of the main loop they connected in
+    pub fn disconnect_all(&mut self) {
+        self.clients.clear();
+    }
+    // Accept new clients and collect every complete request line. Lines that
+    // don't parse are answered with an error right away.
+    pub fn requests(&mut self, epoll: &Epoll) -> Vec<(u64, Command)> {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_err()
+                || epoll
+                    .add(&stream, EpollEvent::new(EpollFlags::EPOLLIN, CLIENT_TOKEN))
+                    .is_err()
+            {
+                continue;
+            }
+            self.clients.push(Client {
+                id: self.next_id,
+                stream,
+                buf: Vec::new(),
+            });
+            self.next_id += 1;
+        }
+        let mut requests = Vec::new();
+        let mut closed = Vec::new();
+        for client in &mut self.clients {
+            let mut chunk = [0; 4096];
+            let open = loop {
+                match client.stream.read(&mut chunk) {
+                    Ok(0) => break false,
+                    Ok(n) => client.buf.extend_from_slice(&chunk[..n]),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break true,
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(_) => break false,
+                }
+            };
+            while let Some(end) = client.buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = client.buf.drain(..=end).collect();
+                if line.trim_ascii().is_empty() {
+                    continue;
+                }
+                match serde_json::from_slice::<Request>(&line) {
+                    Ok(request) if request.version == PROTOCOL_VERSION => {
+                        requests.push((client.id, request.command))
+                    }
+                    Ok(request) => closed.extend(reply_to(
+                        client,
+                        &Response::error(format!(
+                            "unsupported protocol version {}, expected {PROTOCOL_VERSION}",
+                            request.version
+                        )),
+                    )),
+                    Err(err) => closed.extend(reply_to(client, &Response::error(err.to_string()))),
+                }
+            }
+            if !open || client.buf.len() > MAX_LINE {
+                closed.push(client.id);
+            }
+        }
+        self.drop_clients(epoll, &closed);
+        requests
+    }
+    pub fn reply(&mut self, epoll: &Epoll, id: u64, response: &Response) {
+        let Some(client) = self.clients.iter_mut().find(|c| c.id == id) else {
+            return;
+        };
+        if let Some(id) = reply_to(client, response) {
+            self.drop_clients(epoll, &[id]);
+        }
+    }
+    fn drop_clients(&mut self, epoll: &Epoll, ids: &[u64]) {
+        self.clients.retain(|client| {
+            if ids.contains(&client.id) {
+                let _ = epoll.delete(&client.stream);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+impl Response {
+    pub fn ok() -> Response {
+        Response {
+            version: PROTOCOL_VERSION,
+            ok: true,
+            ..Default::default()
+        }
+    }
+    pub fn error(error: impl Into<String>) -> Response {
+        Response {
+            version: PROTOCOL_VERSION,
+            ok: false,
+            error: Some(error.into()),
+            ..Default::default()
+        }
+    }
+}
+
+impl LayerRef {
+    pub fn resolve(&self, cfg: &Config, layer_count: usize) -> Option<usize> {
+        match self {
+            LayerRef::Index(index) => Some(*index).filter(|&i| i < layer_count),
+            LayerRef::Name(name) => cfg.layer_index(name),
+        }
+    }
+}
+
+impl fmt::Display for LayerRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayerRef::Index(index) => write!(f, "{index}"),
+            LayerRef::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+// Returns the client's id if it has to be dropped
+fn reply_to(client: &mut Client, response: &Response) -> Option<u64> {
+    let mut line = serde_json::to_vec(response).unwrap();
+    line.push(b'\n');
+    // Replies can be large, give the client a moment to read them
+    let sent = client
+        .stream
+        .set_nonblocking(false)
+        .and_then(|_| client.stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+        .and_then(|_| client.stream.write_all(&line))
+        .and_then(|_| client.stream.set_nonblocking(true));
+    sent.is_err().then_some(client.id)
+}
+
+// Lowercase hex, for binary data in replies
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,85 @@
+// Wire format of the control socket, shared with tiny-dfr-ctl. Every request and
+// response is a single line of JSON carrying the protocol version.
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const SOCKET_PATH: &str = "/run/tiny-dfr/control.sock";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Request {
+    pub version: u32,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+// Layers are counted from 0, the named ones can also be given by name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum LayerRef {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    SwitchLayer {
+        layer: LayerRef,
+    },
+    SetText {
+        layer: LayerRef,
+        button: usize,
+        text: String,
+    },
+    SetIcon {
+        layer: LayerRef,
+        button: usize,
+        icon: String,
+        theme: Option<String>,
+    },
+    // Hold, Toggle or Lock
+    SetFnMode {
+        mode: String,
+        fn_layer_default: Option<bool>,
+    },
+    State,
+    Reload,
+    Screenshot,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Response {
+    pub version: u32,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<State>,
+    // The panel as shown, a PNG file in hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub png: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct State {
+    pub active_layer: usize,
+    pub fn_mode: String,
+    pub fn_layer_default: bool,
+    pub fn_latched: bool,
+    pub display_connected: bool,
+    pub layers: Vec<LayerState>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LayerState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub buttons: Vec<ButtonState>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ButtonState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub active: bool,
+}
@@ -0,0 +1,134 @@
+use crate::{
+    config::load_font_description,
+    display::{DisplayBackend, Orientation},
+};
+use anyhow::Result;
+use cairo::{Context, Format, ImageSurface};
+use drm::control::ClipRect;
+use pangocairo::{
+    functions::{create_layout, show_layout},
+    pango::{self, EllipsizeMode, FontDescription},
+};
+use std::{
+    backtrace::Backtrace,
+    panic::{self, PanicHookInfo},
+    sync::Mutex,
+};
+
+// Summary of the first panic, later ones are usually fallout from it
+static PANIC_SUMMARY: Mutex<Option<String>> = Mutex::new(None);
+
+fn payload_message(info: &PanicHookInfo) -> String {
+    if let Some(msg) = info.payload().downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = info.payload().downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+// Squash a panic message into something that fits on a single line of the bar
+fn summarize(message: &str) -> String {
+    let message = message
+        .strip_prefix("called `Result::unwrap()` on an `Err` value: ")
+        .or_else(|| message.strip_prefix("called `Option::unwrap()` on a `None` value"))
+        .unwrap_or(message);
+    let summary = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    if summary.is_empty() {
+        "unknown error".to_string()
+    } else {
+        summary
+    }
+}
+
+pub fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        let message = payload_message(info);
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}", l.file(), l.line()))
+            .unwrap_or_default();
+        eprintln!(
+            "tiny-dfr panicked at {location}:\n{message}\n{}",
+            Backtrace::force_capture()
+        );
+        let mut summary = PANIC_SUMMARY.lock().unwrap_or_else(|e| e.into_inner());
+        if summary.is_none() {
+            *summary = Some(summarize(&message));
+        }
+    }));
+}
+
+pub fn take_panic_summary() -> Option<String> {
+    PANIC_SUMMARY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+}
+
+// The config may be what broke, in which case the crash screen falls back to a stock font
+fn crash_font() -> FontDescription {
+    match panic::catch_unwind(load_font_description) {
+        Ok(font) => font,
+        Err(_) => {
+            // Already being reported, don't let it shadow the next failure
+            take_panic_summary();
+            FontDescription::from_string("Sans Bold")
+        }
+    }
+}
+
+pub fn show_crash_screen(
+    display: &mut dyn DisplayBackend,
+    orientation: &Orientation,
+    summary: &str,
+) -> Result<()> {
+    let (disp_width, disp_height) = display.mode_size();
+    let (width, height) = orientation.logical_size((disp_width, disp_height));
+    let (width, height) = (width as f64, height as f64);
+    let (db_width, db_height) = display.buffer_size();
+    let mut surface =
+        ImageSurface::create(Format::ARgb32, db_width as i32, db_height as i32).unwrap();
+    let c = Context::new(&surface).unwrap();
+    c.transform(orientation.matrix(width, height));
+    c.set_source_rgb(0.0, 0.0, 0.0);
+    c.paint().unwrap();
+    let mut font = crash_font();
+
+    let margin = height / 4.0;
+    font.set_absolute_size(height / 2.0 * pango::SCALE as f64);
+    let label = create_layout(&c);
+    label.set_font_description(Some(&font));
+    label.set_text("tiny-dfr crashed:");
+    let (label_width, label_height) = label.pixel_size();
+    c.set_source_rgb(1.0, 0.2, 0.2);
+    c.move_to(margin, (height - label_height as f64) / 2.0);
+    show_layout(&c, &label);
+
+    // Long messages get a smaller font before they get cut off
+    let left = margin * 2.0 + label_width as f64;
+    let max_width = width - left - margin;
+    let message = create_layout(&c);
+    message.set_text(summary);
+    let mut font_size = height / 2.0;
+    loop {
+        font.set_absolute_size(font_size * pango::SCALE as f64);
+        message.set_font_description(Some(&font));
+        if font_size <= height / 4.0 || message.pixel_size().0 as f64 <= max_width {
+            break;
+        }
+        font_size -= 1.0;
+    }
+    message.set_width((max_width * pango::SCALE as f64) as i32);
+    message.set_ellipsize(EllipsizeMode::End);
+    c.set_source_rgb(1.0, 1.0, 1.0);
+    c.move_to(left, (height - message.pixel_size().1 as f64) / 2.0);
+    show_layout(&c, &message);
+    drop(c);
+
+    display.present(
+        &surface.data().unwrap(),
+        &[ClipRect::new(0, 0, disp_width, disp_height)],
+    )
+}
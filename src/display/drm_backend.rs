@@ -1,20 +1,22 @@
+use super::DisplayBackend;
 use anyhow::{Result, anyhow};
 use drm::{
     ClientCapability, Device as DrmDevice,
-    buffer::DrmFourcc,
+    buffer::{Buffer, DrmFourcc},
     control::{
-        AtomicCommitFlags, ClipRect, Device as ControlDevice, Mode, ResourceHandle, atomic,
-        connector,
-        dumbbuffer::{DumbBuffer, DumbMapping},
-        framebuffer, property,
+        AtomicCommitFlags, ClipRect, Device as ControlDevice, Event, Mode, ResourceHandle, atomic,
+        connector, dumbbuffer::DumbBuffer, framebuffer, plane, property,
     },
 };
 use std::{
     fs::{self, File, OpenOptions},
     os::unix::io::{AsFd, BorrowedFd},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
+// Number of dumb buffers in the swap chain
+const SWAP_CHAIN_LEN: usize = 2;
+
 struct Card(File);
 impl AsFd for Card {
     fn as_fd(&self) -> BorrowedFd<'_> {
@@ -35,17 +37,31 @@ impl Card {
     }
 }
 
+struct SwapBuffer {
+    db: DumbBuffer,
+    fb: framebuffer::Handle,
+    // Regions presented since this buffer was last written to
+    damage: Vec<ClipRect>,
+}
+
 pub struct DrmBackend {
     card: Card,
+    path: PathBuf,
     mode: Mode,
-    db: DumbBuffer,
-    fb: framebuffer::Handle,
+    plane: plane::Handle,
+    fb_id_prop: property::Handle,
+    buffers: Vec<SwapBuffer>,
+    front: usize,
+    flip_pending: bool,
 }
 
 impl Drop for DrmBackend {
     fn drop(&mut self) {
-        self.card.destroy_framebuffer(self.fb).unwrap();
-        self.card.destroy_dumb_buffer(self.db).unwrap();
+        // These fail once the device is gone, which takes the buffers with it anyway
+        for buffer in self.buffers.drain(..) {
+            let _ = self.card.destroy_framebuffer(buffer.fb);
+            let _ = self.card.destroy_dumb_buffer(buffer.db);
+        }
     }
 }
 
@@ -64,7 +80,7 @@ fn find_prop_id<T: ResourceHandle>(
     Err(anyhow!("Property not found"))
 }
 
-fn try_open_card(path: &Path) -> Result<DrmBackend> {
+fn try_open_card(path: &Path, min_aspect_ratio: u16) -> Result<DrmBackend> {
     let card = Card::open(path)?;
     card.set_client_capability(ClientCapability::UniversalPlanes, true)?;
     card.set_client_capability(ClientCapability::Atomic, true)?;
@@ -89,14 +105,26 @@ fn try_open_card(path: &Path) -> Result<DrmBackend> {
 
     let &mode = con.modes().first().ok_or(anyhow!("No modes found"))?;
     let (disp_width, disp_height) = mode.size();
-    if disp_height / disp_width < 30 {
+    let (long_side, short_side) = (disp_width.max(disp_height), disp_width.min(disp_height));
+    if long_side / short_side.max(1) < min_aspect_ratio {
         return Err(anyhow!("This does not look like a touchbar"));
     }
     let crtc = crtcinfo.first().ok_or(anyhow!("No crtcs found"))?;
     let fmt = DrmFourcc::Xrgb8888;
-    let db = card.create_dumb_buffer((64, disp_height.into()), fmt, 32)?;
-
-    let fb = card.add_framebuffer(&db, 24, 32)?;
+    let mut buffers = Vec::with_capacity(SWAP_CHAIN_LEN);
+    for _ in 0..SWAP_CHAIN_LEN {
+        let db = card.create_dumb_buffer(
+            (disp_width.next_multiple_of(64).into(), disp_height.into()),
+            fmt,
+            32,
+        )?;
+        let fb = card.add_framebuffer(&db, 24, 32)?;
+        buffers.push(SwapBuffer {
+            db,
+            fb,
+            damage: Vec::new(),
+        });
+    }
     let plane = *card
         .plane_handles()?
         .first()
@@ -120,10 +148,11 @@ fn try_open_card(path: &Path) -> Result<DrmBackend> {
         find_prop_id(&card, crtc.handle(), "ACTIVE")?,
         property::Value::Boolean(true),
     );
+    let fb_id_prop = find_prop_id(&card, plane, "FB_ID")?;
     atomic_req.add_property(
         plane,
-        find_prop_id(&card, plane, "FB_ID")?,
-        property::Value::Framebuffer(Some(fb)),
+        fb_id_prop,
+        property::Value::Framebuffer(Some(buffers[0].fb)),
     );
     atomic_req.add_property(
         plane,
@@ -173,18 +202,27 @@ fn try_open_card(path: &Path) -> Result<DrmBackend> {
 
     card.atomic_commit(AtomicCommitFlags::ALLOW_MODESET, atomic_req)?;
 
-    Ok(DrmBackend { card, mode, db, fb })
+    Ok(DrmBackend {
+        card,
+        path: path.to_owned(),
+        mode,
+        plane,
+        fb_id_prop,
+        buffers,
+        front: 0,
+        flip_pending: false,
+    })
 }
 
 impl DrmBackend {
-    pub fn open_card() -> Result<DrmBackend> {
+    pub fn open_card(min_aspect_ratio: u16) -> Result<DrmBackend> {
         let mut errors = Vec::new();
         for entry in fs::read_dir("/dev/dri/")? {
             let entry = entry?;
             if !entry.file_name().to_string_lossy().starts_with("card") {
                 continue;
             }
-            match try_open_card(&entry.path()) {
+            match try_open_card(&entry.path(), min_aspect_ratio) {
                 Ok(card) => return Ok(card),
                 Err(err) => errors.push(format!(
                     "{}: {}",
@@ -198,16 +236,81 @@ impl DrmBackend {
             errors.join(",\n    ")
         ))
     }
-    pub fn mode(&self) -> Mode {
-        self.mode
+}
+
+impl DrmBackend {
+    // Blocks on the card fd, so only call this when it is readable or a flip is pending
+    fn receive_flips(&mut self) -> Result<()> {
+        for event in self.card.receive_events()? {
+            if let Event::PageFlip(_) = event {
+                self.flip_pending = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Copy the given region of a frame into a dumb buffer with a (possibly) different pitch
+fn copy_region(dst: &mut [u8], dst_pitch: usize, src: &[u8], src_pitch: usize, clip: &ClipRect) {
+    let rows = src.len() / src_pitch;
+    let x1 = (clip.x1() as usize * 4).min(src_pitch);
+    let x2 = (clip.x2() as usize * 4).min(src_pitch);
+    for y in (clip.y1() as usize)..(clip.y2() as usize).min(rows) {
+        dst[y * dst_pitch + x1..y * dst_pitch + x2]
+            .copy_from_slice(&src[y * src_pitch + x1..y * src_pitch + x2]);
+    }
+}
+
+impl DisplayBackend for DrmBackend {
+    fn mode_size(&self) -> (u16, u16) {
+        self.mode.size()
+    }
+    fn buffer_size(&self) -> (u32, u32) {
+        self.buffers[0].db.size()
+    }
+    fn present(&mut self, data: &[u8], clips: &[ClipRect]) -> Result<()> {
+        while self.flip_pending {
+            self.receive_flips()?;
+        }
+        let back = (self.front + 1) % self.buffers.len();
+        for buffer in &mut self.buffers {
+            buffer.damage.extend_from_slice(clips);
+        }
+
+        // Only the regions that changed since this buffer was last shown need copying
+        let src_pitch = self.buffer_size().0 as usize * 4;
+        let buffer = &mut self.buffers[back];
+        let dst_pitch = buffer.db.pitch() as usize;
+        let mut map = self.card.map_dumb_buffer(&mut buffer.db)?;
+        for clip in buffer.damage.drain(..) {
+            copy_region(map.as_mut(), dst_pitch, data, src_pitch, &clip);
+        }
+        drop(map);
+
+        let mut atomic_req = atomic::AtomicModeReq::new();
+        atomic_req.add_property(
+            self.plane,
+            self.fb_id_prop,
+            property::Value::Framebuffer(Some(buffer.fb)),
+        );
+        self.card.atomic_commit(
+            AtomicCommitFlags::PAGE_FLIP_EVENT | AtomicCommitFlags::NONBLOCK,
+            atomic_req,
+        )?;
+        self.front = back;
+        self.flip_pending = true;
+        Ok(())
+    }
+    fn fd(&self) -> Option<BorrowedFd<'_>> {
+        Some(self.card.as_fd())
     }
-    pub fn fb_info(&self) -> Result<framebuffer::Info> {
-        Ok(self.card.get_framebuffer(self.fb)?)
+    fn process_events(&mut self) -> Result<()> {
+        self.receive_flips()
     }
-    pub fn dirty(&self, clips: &[ClipRect]) -> Result<()> {
-        Ok(self.card.dirty_framebuffer(self.fb, clips)?)
+    fn ready(&self) -> bool {
+        !self.flip_pending
     }
-    pub fn map(&mut self) -> Result<DumbMapping> {
-        Ok(self.card.map_dumb_buffer(&mut self.db)?)
+    fn devnode(&self) -> Option<&Path> {
+        Some(&self.path)
     }
 }
@@ -0,0 +1,110 @@
+use super::{DisplayBackend, Orientation};
+use anyhow::Result;
+use cairo::{Format, ImageSurface};
+use drm::control::ClipRect;
+use std::{fs::File, path::Path};
+
+// Renders into an in-memory buffer laid out the same way as the touch bar's
+// dumb buffer, so the rest of the daemon can't tell the difference.
+pub struct HeadlessBackend {
+    mode_size: (u16, u16),
+    buffer_size: (u32, u32),
+    buffer: Vec<u8>,
+}
+
+impl HeadlessBackend {
+    pub fn new(mode_size: (u16, u16)) -> HeadlessBackend {
+        let (disp_width, disp_height) = mode_size;
+        let buffer_size = (disp_width.next_multiple_of(64) as u32, disp_height as u32);
+        HeadlessBackend {
+            mode_size,
+            buffer_size,
+            buffer: vec![0; (buffer_size.0 * buffer_size.1 * 4) as usize],
+        }
+    }
+    // Write the last presented frame as it would appear on the panel
+    pub fn write_png(&self, path: impl AsRef<Path>, orientation: &Orientation) -> Result<()> {
+        let (width, height) = orientation.logical_size(self.mode_size);
+        let (db_width, db_height) = self.buffer_size;
+        let frame = ImageSurface::create_for_data(
+            self.buffer.clone(),
+            Format::Rgb24,
+            db_width as i32,
+            db_height as i32,
+            db_width as i32 * 4,
+        )?;
+        let out = orientation.upright(&frame, width, height)?;
+        out.write_to_png(&mut File::create(path)?)?;
+        Ok(())
+    }
+}
+
+impl DisplayBackend for HeadlessBackend {
+    fn mode_size(&self) -> (u16, u16) {
+        self.mode_size
+    }
+    fn buffer_size(&self) -> (u32, u32) {
+        self.buffer_size
+    }
+    fn present(&mut self, data: &[u8], _clips: &[ClipRect]) -> Result<()> {
+        self.buffer[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::load_config_from, temp_dir::TempDir};
+
+    // The Fn layer from the packaged config needs icons that are only there once installed
+    const USER_CONFIG: &str = r##"
+FnLayerKeys = [{ Text = "Mute", Action = "Mute" }]
+[Theme]
+Background = "#000000"
+ButtonFill = "#00ff00"
+"##;
+
+    #[test]
+    fn draws_layer_upright() {
+        let (width, height) = (2170, 60);
+        let base = include_str!("../../share/tiny-dfr/config.toml");
+        let orientation = Orientation::default();
+        let mut display = HeadlessBackend::new(orientation.mode_size((width, height)));
+        let (mut cfg, mut layers) = load_config_from(base, display.mode_size(), Some(USER_CONFIG));
+        cfg.enable_pixel_shift = false;
+        let (db_width, db_height) = display.buffer_size();
+        assert_eq!((db_width, db_height), (64, 2170));
+        let mut surface =
+            ImageSurface::create(Format::ARgb32, db_width as i32, db_height as i32).unwrap();
+        let clips = layers[0].draw(
+            &cfg,
+            width as i32,
+            height as i32,
+            &surface,
+            (0.0, 0.0),
+            true,
+        );
+        display.present(&surface.data().unwrap(), &clips).unwrap();
+
+        let dir = TempDir::new("headless");
+        let path = dir.join("layer.png");
+        display.write_png(&path, &cfg.orientation).unwrap();
+        let mut png = ImageSurface::create_from_png(&mut File::open(&path).unwrap()).unwrap();
+        assert_eq!((png.width(), png.height()), (width as i32, height as i32));
+
+        // Across the middle, the buttons are filled and separated by the background
+        let stride = png.stride() as usize;
+        let data = png.data().unwrap();
+        let row = &data[stride * (height as usize / 2)..][..width as usize * 4];
+        let pixels: Vec<_> = row.chunks(4).map(|p| (p[2], p[1], p[0])).collect();
+        let filled = pixels.iter().filter(|&&p| p == (0, 255, 0)).count();
+        assert!(filled > width as usize / 2, "only {filled} filled pixels");
+        let buttons = pixels
+            .chunk_by(|a, b| (*a == (0, 0, 0)) == (*b == (0, 0, 0)))
+            .filter(|run| run[0] != (0, 0, 0))
+            .count();
+        // Esc and F1 to F12
+        assert_eq!(buttons, 13);
+    }
+}
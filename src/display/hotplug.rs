@@ -0,0 +1,54 @@
+use anyhow::Result;
+use std::{
+    os::fd::AsFd,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use udev::{EventType, MonitorBuilder, MonitorSocket};
+
+// How often to look for a touch bar while none is attached, in case we missed the udev event
+pub const DISPLAY_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+pub enum HotplugEvent {
+    Added,
+    Removed(PathBuf),
+}
+
+pub struct DrmHotplug {
+    socket: MonitorSocket,
+    last_attempt: Instant,
+}
+
+impl DrmHotplug {
+    pub fn new() -> Result<DrmHotplug> {
+        let socket = MonitorBuilder::new()?.match_subsystem("drm")?.listen()?;
+        Ok(DrmHotplug {
+            socket,
+            last_attempt: Instant::now(),
+        })
+    }
+    pub fn events(&self) -> Vec<HotplugEvent> {
+        self.socket
+            .iter()
+            .filter_map(|evt| match evt.event_type() {
+                EventType::Add | EventType::Change => Some(HotplugEvent::Added),
+                EventType::Remove => evt.devnode().map(|p| HotplugEvent::Removed(p.to_owned())),
+                _ => None,
+            })
+            .collect()
+    }
+    // Returns true if it is time to try reopening the display again
+    pub fn should_retry(&mut self) -> bool {
+        if self.last_attempt.elapsed() < DISPLAY_RETRY_INTERVAL {
+            return false;
+        }
+        self.last_attempt = Instant::now();
+        true
+    }
+    pub fn next_retry_time(&self) -> Instant {
+        self.last_attempt + DISPLAY_RETRY_INTERVAL
+    }
+    pub fn fd(&self) -> &impl AsFd {
+        &self.socket
+    }
+}
@@ -0,0 +1,39 @@
+mod drm_backend;
+mod headless;
+mod hotplug;
+mod orientation;
+
+use anyhow::Result;
+use drm::control::ClipRect;
+use std::{os::fd::BorrowedFd, path::Path};
+
+pub use self::drm_backend::DrmBackend;
+pub use self::headless::HeadlessBackend;
+pub use self::hotplug::*;
+pub use self::orientation::Orientation;
+
+pub trait DisplayBackend {
+    // Size of the active mode as (hdisplay, vdisplay)
+    fn mode_size(&self) -> (u16, u16);
+    // Size of the buffer that gets scanned out, in pixels
+    fn buffer_size(&self) -> (u32, u32);
+    // Queue a complete XRGB8888 frame for scanout, only the damaged regions are guaranteed
+    // to be copied. Waits for the previous frame to be shown if needed.
+    fn present(&mut self, data: &[u8], clips: &[ClipRect]) -> Result<()>;
+    // Fd to poll for completion events, if the backend has one
+    fn fd(&self) -> Option<BorrowedFd<'_>> {
+        None
+    }
+    // Handle pending events, call when fd() becomes readable
+    fn process_events(&mut self) -> Result<()> {
+        Ok(())
+    }
+    // Whether a frame can be presented right now without blocking
+    fn ready(&self) -> bool {
+        true
+    }
+    // Device node backing this display, used to match hotplug events
+    fn devnode(&self) -> Option<&Path> {
+        None
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::Result;
+use cairo::{Context, Format, ImageSurface, Matrix};
+use drm::control::ClipRect;
+
+// How the logical, landscape layout maps onto the panel's scanout buffer.
+// Rotation is applied after the flips, and the touch bar panels scan out in portrait,
+// hence the default of 90 degrees.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Orientation {
+    pub rotation: u16,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Orientation {
+            rotation: 90,
+            flip_horizontal: false,
+            flip_vertical: false,
+        }
+    }
+}
+
+impl Orientation {
+    pub fn new(rotation: u16, flip_horizontal: bool, flip_vertical: bool) -> Orientation {
+        let rotation = if [0, 90, 180, 270].contains(&rotation) {
+            rotation
+        } else {
+            println!("Rotation must be one of 0, 90, 180 or 270");
+            90
+        };
+        Orientation {
+            rotation,
+            flip_horizontal,
+            flip_vertical,
+        }
+    }
+    fn swaps_axes(&self) -> bool {
+        self.rotation == 90 || self.rotation == 270
+    }
+    // Logical (width, height) for a given mode size, and vice versa
+    pub fn logical_size(&self, mode_size: (u16, u16)) -> (u16, u16) {
+        if self.swaps_axes() {
+            (mode_size.1, mode_size.0)
+        } else {
+            mode_size
+        }
+    }
+    pub fn mode_size(&self, logical_size: (u16, u16)) -> (u16, u16) {
+        self.logical_size(logical_size)
+    }
+    // Transform from logical coordinates to scanout buffer coordinates
+    pub fn matrix(&self, width: f64, height: f64) -> Matrix {
+        let flip = Matrix::new(
+            if self.flip_horizontal { -1.0 } else { 1.0 },
+            0.0,
+            0.0,
+            if self.flip_vertical { -1.0 } else { 1.0 },
+            if self.flip_horizontal { width } else { 0.0 },
+            if self.flip_vertical { height } else { 0.0 },
+        );
+        let rotate = match self.rotation {
+            90 => Matrix::new(0.0, 1.0, -1.0, 0.0, height, 0.0),
+            180 => Matrix::new(-1.0, 0.0, 0.0, -1.0, width, height),
+            270 => Matrix::new(0.0, -1.0, 1.0, 0.0, 0.0, width),
+            _ => Matrix::identity(),
+        };
+        Matrix::multiply(&flip, &rotate)
+    }
+    // The digitizer is attached to the panel, so touches only need to follow the parts
+    // of the transform that move content around physically: turning the panel upside
+    // down and mirroring it. Whether the panel scans out in portrait doesn't matter.
+    pub fn touch_to_logical(&self, x: f64, y: f64, width: f64, height: f64) -> (f64, f64) {
+        let upside_down = self.rotation >= 180;
+        let x = if upside_down != self.flip_horizontal {
+            width - x
+        } else {
+            x
+        };
+        let y = if upside_down != self.flip_vertical {
+            height - y
+        } else {
+            y
+        };
+        (x, y)
+    }
+    // Turn a frame laid out for scanout back into the logical layout it was drawn in
+    pub fn upright(&self, frame: &ImageSurface, width: u16, height: u16) -> Result<ImageSurface> {
+        let out = ImageSurface::create(Format::Rgb24, width as i32, height as i32)?;
+        let c = Context::new(&out)?;
+        c.transform(self.matrix(width as f64, height as f64).try_invert()?);
+        c.set_source_surface(frame, 0.0, 0.0)?;
+        c.paint()?;
+        drop(c);
+        Ok(out)
+    }
+    // Bounding box of a logical rectangle in scanout buffer coordinates
+    pub fn clip(&self, width: f64, height: f64, x: f64, y: f64, w: f64, h: f64) -> ClipRect {
+        let m = self.matrix(width, height);
+        let (x1, y1) = m.transform_point(x, y);
+        let (x2, y2) = m.transform_point(x + w, y + h);
+        ClipRect::new(
+            x1.min(x2).max(0.0) as u16,
+            y1.min(y2).max(0.0) as u16,
+            x1.max(x2).max(0.0) as u16,
+            y1.max(y2).max(0.0) as u16,
+        )
+    }
+}
@@ -0,0 +1,255 @@
+use crate::config::Config;
+use cairo::{Context, Surface};
+use drm::control::ClipRect;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+// Fn released sooner than this, with no other key in between, is a tap
+const TAP_TIME: Duration = Duration::from_millis(300);
+// Longest gap between the two taps that latch the Fn layer in Lock mode
+const DOUBLE_TAP_GAP: Duration = Duration::from_millis(400);
+
+// How Fn gets to the other layer. Holding Fn always shows it while held,
+// the modes differ in how it can be made to stay.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum FnMode {
+    // Only while held
+    #[default]
+    Hold,
+    // A tap switches over until the next tap
+    Toggle,
+    // A double tap switches over until the next tap
+    Lock,
+}
+
+impl FnMode {
+    pub fn parse(name: &str) -> Option<FnMode> {
+        match name.to_ascii_lowercase().as_str() {
+            "hold" => Some(FnMode::Hold),
+            "toggle" => Some(FnMode::Toggle),
+            "lock" => Some(FnMode::Lock),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            FnMode::Hold => "Hold",
+            FnMode::Toggle => "Toggle",
+            FnMode::Lock => "Lock",
+        }
+    }
+}
+
+// Which of the primary and Fn layers should be shown, going by the Fn key
+pub struct FnKey {
+    pub mode: FnMode,
+    // The Fn layer is shown unless Fn is held or latched
+    pub fn_layer_default: bool,
+    held: bool,
+    // Switched over to the other layer until the next tap
+    latched: bool,
+    pressed_at: Option<Instant>,
+    last_tap: Option<Instant>,
+}
+
+impl FnKey {
+    pub fn new(mode: FnMode, fn_layer_default: bool) -> FnKey {
+        FnKey {
+            mode,
+            fn_layer_default,
+            held: false,
+            latched: false,
+            pressed_at: None,
+            last_tap: None,
+        }
+    }
+    // Switch modes, dropping any latch
+    pub fn configure(&mut self, mode: FnMode, fn_layer_default: bool) {
+        *self = FnKey {
+            held: self.held,
+            ..FnKey::new(mode, fn_layer_default)
+        };
+    }
+    pub fn press(&mut self, time: Instant) {
+        self.held = true;
+        self.pressed_at = Some(time);
+    }
+    pub fn release(&mut self, time: Instant) {
+        self.held = false;
+        let tapped = self.pressed_at.take().is_some_and(|t| time - t <= TAP_TIME);
+        if !tapped {
+            self.last_tap = None;
+            return;
+        }
+        match self.mode {
+            FnMode::Hold => {}
+            FnMode::Toggle => self.latched = !self.latched,
+            FnMode::Lock if self.latched => self.latched = false,
+            FnMode::Lock => {
+                if self.last_tap.is_some_and(|t| time - t <= DOUBLE_TAP_GAP) {
+                    self.latched = true;
+                    self.last_tap = None;
+                    return;
+                }
+            }
+        }
+        self.last_tap = Some(time);
+    }
+    // Another key or a button was pressed, so Fn is being used as a modifier
+    pub fn interrupt(&mut self) {
+        self.pressed_at = None;
+        self.last_tap = None;
+    }
+    // Latch or unlatch the other layer, regardless of mode
+    pub fn toggle_latch(&mut self) {
+        self.latched = !self.latched;
+    }
+    pub fn latched(&self) -> bool {
+        self.latched
+    }
+    // Holding Fn while latched shows the default layer for as long as it is held
+    pub fn shows_fn_layer(&self) -> bool {
+        self.held ^ self.latched ^ self.fn_layer_default
+    }
+    // The layer to show, given the one shown when the Fn layer isn't
+    pub fn layer(&self, base_layer: usize) -> usize {
+        if self.shows_fn_layer() { 1 } else { base_layer }
+    }
+}
+
+// A short bar along the bottom edge, shown on top of the layer while latched
+pub fn draw_latch_indicator(
+    config: &Config,
+    width: i32,
+    height: i32,
+    surface: &Surface,
+) -> ClipRect {
+    let (width, height) = (width as f64, height as f64);
+    let c = Context::new(surface).unwrap();
+    c.transform(config.orientation.matrix(width, height));
+    let bar_width = config.layout.icon_size as f64;
+    let thickness = (height / 20.0).round().max(2.0);
+    let (x, y) = ((width - bar_width) / 2.0, height - thickness * 1.5);
+    config.theme.text.set_source(&c);
+    c.rectangle(x, y, bar_width, thickness);
+    c.fill().unwrap();
+    config
+        .orientation
+        .clip(width, height, x, y, bar_width, thickness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHORT: Duration = Duration::from_millis(100);
+
+    // Press and release Fn at the given offsets
+    fn tap(fn_key: &mut FnKey, start: Instant, press: Duration, release: Duration) {
+        fn_key.press(start + press);
+        fn_key.release(start + release);
+    }
+
+    #[test]
+    fn tap_and_hold() {
+        let start = Instant::now();
+        let mut fn_key = FnKey::new(FnMode::Toggle, false);
+        fn_key.press(start);
+        assert!(fn_key.shows_fn_layer());
+        assert_eq!(fn_key.layer(3), 1);
+        // Held longer than a tap, the layer goes away with the key
+        fn_key.release(start + TAP_TIME + SHORT);
+        assert!(!fn_key.latched());
+        assert_eq!(fn_key.layer(3), 3);
+        // Exactly TAP_TIME still counts as a tap
+        tap(&mut fn_key, start, Duration::ZERO, TAP_TIME);
+        assert!(fn_key.latched());
+    }
+
+    #[test]
+    fn hold_never_latches() {
+        let start = Instant::now();
+        let mut fn_key = FnKey::new(FnMode::Hold, false);
+        tap(&mut fn_key, start, Duration::ZERO, SHORT);
+        tap(&mut fn_key, start, SHORT * 2, SHORT * 3);
+        assert!(!fn_key.latched());
+        assert!(!fn_key.shows_fn_layer());
+    }
+
+    #[test]
+    fn toggle_flips_on_every_tap() {
+        let start = Instant::now();
+        let mut fn_key = FnKey::new(FnMode::Toggle, false);
+        tap(&mut fn_key, start, Duration::ZERO, SHORT);
+        assert!(fn_key.shows_fn_layer());
+        // Holding Fn while latched shows the primary layer
+        fn_key.press(start + SHORT * 10);
+        assert!(!fn_key.shows_fn_layer());
+        fn_key.release(start + SHORT * 11);
+        assert!(!fn_key.latched());
+        assert!(!fn_key.shows_fn_layer());
+    }
+
+    #[test]
+    fn lock_needs_a_double_tap() {
+        let start = Instant::now();
+        let mut fn_key = FnKey::new(FnMode::Lock, false);
+        tap(&mut fn_key, start, Duration::ZERO, SHORT);
+        assert!(!fn_key.latched());
+        // The second tap comes too late, but can start a new double tap
+        let late = SHORT + DOUBLE_TAP_GAP + SHORT;
+        tap(&mut fn_key, start, late - SHORT, late);
+        assert!(!fn_key.latched());
+        tap(&mut fn_key, start, late + SHORT, late + SHORT * 2);
+        assert!(fn_key.latched());
+        assert!(fn_key.shows_fn_layer());
+        // A single tap unlatches
+        tap(&mut fn_key, start, late + SHORT * 10, late + SHORT * 11);
+        assert!(!fn_key.latched());
+        assert!(!fn_key.shows_fn_layer());
+    }
+
+    #[test]
+    fn interrupt_cancels_a_tap() {
+        let start = Instant::now();
+        let mut fn_key = FnKey::new(FnMode::Toggle, false);
+        fn_key.press(start);
+        fn_key.interrupt();
+        fn_key.release(start + SHORT);
+        assert!(!fn_key.latched());
+
+        let mut fn_key = FnKey::new(FnMode::Lock, false);
+        tap(&mut fn_key, start, Duration::ZERO, SHORT);
+        fn_key.interrupt();
+        tap(&mut fn_key, start, SHORT * 2, SHORT * 3);
+        assert!(!fn_key.latched());
+    }
+
+    #[test]
+    fn fn_layer_default_inverts() {
+        let start = Instant::now();
+        let mut fn_key = FnKey::new(FnMode::Toggle, true);
+        assert!(fn_key.shows_fn_layer());
+        assert_eq!(fn_key.layer(0), 1);
+        fn_key.press(start);
+        assert!(!fn_key.shows_fn_layer());
+        fn_key.release(start + SHORT);
+        // Latched, so the primary layer stays
+        assert!(fn_key.latched());
+        assert!(!fn_key.shows_fn_layer());
+        assert_eq!(fn_key.layer(0), 0);
+        fn_key.press(start + SHORT * 10);
+        assert!(fn_key.shows_fn_layer());
+    }
+
+    #[test]
+    fn configure_drops_the_latch() {
+        let start = Instant::now();
+        let mut fn_key = FnKey::new(FnMode::Toggle, false);
+        tap(&mut fn_key, start, Duration::ZERO, SHORT);
+        fn_key.press(start + SHORT * 10);
+        fn_key.configure(FnMode::Lock, false);
+        assert!(!fn_key.latched());
+        assert!(fn_key.shows_fn_layer());
+    }
+}
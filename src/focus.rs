@@ -0,0 +1,358 @@
+// Follows which application has focus through the compositor's IPC socket.
+// Apps are named by their Wayland app_id, or X11 class for XWayland windows.
+// An empty name means nothing is focused.
+use serde_json::Value;
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+};
+
+const I3_MAGIC: &[u8] = b"i3-ipc";
+const I3_SUBSCRIBE: u32 = 2;
+const I3_GET_TREE: u32 = 4;
+const I3_EVENT_WORKSPACE: u32 = 0x8000_0000;
+const I3_EVENT_WINDOW: u32 = 0x8000_0003;
+// Largest message read from the compositor, far more than any real tree of windows
+const MAX_MESSAGE_LEN: usize = 16 << 20;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Compositor {
+    Sway,
+    Hyprland,
+}
+
+// The IPC socket of a compositor running in the given XDG_RUNTIME_DIR
+pub fn find_socket(runtime_dir: &Path) -> Option<(Compositor, PathBuf)> {
+    let entries = |dir: &Path| {
+        fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok().map(|e| e.path()))
+    };
+    let sway = entries(runtime_dir).find(|path| {
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with("sway-ipc.") && n.ends_with(".sock"))
+    });
+    if let Some(path) = sway {
+        return Some((Compositor::Sway, path));
+    }
+    entries(&runtime_dir.join("hypr"))
+        .map(|instance| instance.join(".socket2.sock"))
+        .find(|path| path.exists())
+        .map(|path| (Compositor::Hyprland, path))
+}
+
+// Patterns match the whole name, * stands for any run of characters
+pub fn app_matches(pattern: &str, app: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == app,
+        Some((prefix, rest)) => {
+            let Some(app) = app.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=app.len())
+                .filter(|&i| app.is_char_boundary(i))
+                .any(|i| app_matches(rest, &app[i..]))
+        }
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+fn i3_send(stream: &mut UnixStream, kind: u32, payload: &[u8]) -> io::Result<()> {
+    let mut msg = I3_MAGIC.to_vec();
+    msg.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    msg.extend_from_slice(&kind.to_ne_bytes());
+    msg.extend_from_slice(payload);
+    stream.write_all(&msg)
+}
+
+fn i3_receive(stream: &mut impl Read) -> io::Result<(u32, Value)> {
+    let mut header = [0; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != I3_MAGIC {
+        return Err(invalid("not an i3 IPC message"));
+    }
+    let len = u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize;
+    let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+    if len > MAX_MESSAGE_LEN {
+        return Err(invalid(format!(
+            "i3 IPC message of {len} bytes is too long"
+        )));
+    }
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload)?;
+    let value = serde_json::from_slice(&payload).map_err(|err| invalid(err.to_string()))?;
+    Ok((kind, value))
+}
+
+// app_id, falling back to the X11 class
+fn sway_app(container: &Value) -> String {
+    container["app_id"]
+        .as_str()
+        .or_else(|| container["window_properties"]["class"].as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn sway_focused(node: &Value) -> Option<String> {
+    if node["focused"].as_bool() == Some(true) {
+        return Some(sway_app(node));
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(sway_focused)
+}
+
+fn hyprland_request(event_socket: &Path, request: &str) -> io::Result<Value> {
+    let mut stream = UnixStream::connect(event_socket.with_file_name(".socket.sock"))?;
+    stream.write_all(request.as_bytes())?;
+    let mut reply = Vec::new();
+    stream
+        .take(MAX_MESSAGE_LEN as u64 + 1)
+        .read_to_end(&mut reply)?;
+    if reply.len() > MAX_MESSAGE_LEN {
+        return Err(invalid("Hyprland reply is too long"));
+    }
+    serde_json::from_slice(&reply).map_err(|err| invalid(err.to_string()))
+}
+
+pub struct FocusWatcher {
+    compositor: Compositor,
+    stream: BufReader<UnixStream>,
+}
+
+impl FocusWatcher {
+    // Subscribe to focus changes, returns the watcher and the app focused right now
+    pub fn connect(compositor: Compositor, path: &Path) -> io::Result<(FocusWatcher, String)> {
+        let mut stream = UnixStream::connect(path)?;
+        let focused = match compositor {
+            Compositor::Sway => {
+                i3_send(&mut stream, I3_SUBSCRIBE, br#"["window","workspace"]"#)?;
+                let (_, reply) = i3_receive(&mut stream)?;
+                if reply["success"].as_bool() != Some(true) {
+                    return Err(invalid("sway refused the subscription"));
+                }
+                i3_send(&mut stream, I3_GET_TREE, b"")?;
+                // Events may already be arriving, skip them until the tree shows up
+                loop {
+                    let (kind, tree) = i3_receive(&mut stream)?;
+                    if kind == I3_GET_TREE {
+                        break sway_focused(&tree).unwrap_or_default();
+                    }
+                }
+            }
+            Compositor::Hyprland => {
+                let window = hyprland_request(path, "j/activewindow")?;
+                window["class"].as_str().unwrap_or_default().to_string()
+            }
+        };
+        let watcher = FocusWatcher {
+            compositor,
+            stream: BufReader::new(stream),
+        };
+        Ok((watcher, focused))
+    }
+    // Block until focus moves to another app
+    pub fn next_focus(&mut self) -> io::Result<String> {
+        loop {
+            match self.compositor {
+                Compositor::Sway => {
+                    let (kind, event) = i3_receive(&mut self.stream)?;
+                    if event["change"] != "focus" {
+                        continue;
+                    }
+                    match kind {
+                        I3_EVENT_WINDOW => return Ok(sway_app(&event["container"])),
+                        // Focus moving to an empty workspace leaves no window focused
+                        I3_EVENT_WORKSPACE => {
+                            let workspace = &event["current"];
+                            let empty = ["nodes", "floating_nodes"]
+                                .iter()
+                                .all(|key| workspace[key].as_array().is_none_or(Vec::is_empty));
+                            if empty {
+                                return Ok(String::new());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Compositor::Hyprland => {
+                    let mut line = String::new();
+                    let limit = MAX_MESSAGE_LEN as u64;
+                    match (&mut self.stream).take(limit).read_line(&mut line)? {
+                        0 => return Err(ErrorKind::UnexpectedEof.into()),
+                        n if n as u64 == limit => {
+                            return Err(invalid("Hyprland event is too long"));
+                        }
+                        _ => {}
+                    }
+                    // activewindow>>class,title
+                    if let Some(window) = line.trim_end().strip_prefix("activewindow>>") {
+                        let class = window.split_once(',').map_or(window, |(class, _)| class);
+                        return Ok(class.to_string());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use std::{io::Cursor, os::unix::net::UnixListener, thread};
+
+    fn i3_message(kind: u32, payload: &str) -> Vec<u8> {
+        let mut msg = I3_MAGIC.to_vec();
+        msg.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        msg.extend_from_slice(&kind.to_ne_bytes());
+        msg.extend_from_slice(payload.as_bytes());
+        msg
+    }
+
+    fn i3_read_request(stream: &mut UnixStream) -> (u32, String) {
+        let mut header = [0; 14];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(&header[..6], I3_MAGIC);
+        let len = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+        let kind = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+        let mut payload = vec![0; len as usize];
+        stream.read_exact(&mut payload).unwrap();
+        (kind, String::from_utf8(payload).unwrap())
+    }
+
+    #[test]
+    fn follows_sway() {
+        let dir = TempDir::new("focus-sway");
+        let path = dir.join("sway-ipc.1000.1234.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (kind, payload) = i3_read_request(&mut stream);
+            assert_eq!(
+                (kind, payload.as_str()),
+                (I3_SUBSCRIBE, r#"["window","workspace"]"#)
+            );
+            stream
+                .write_all(&i3_message(I3_SUBSCRIBE, r#"{"success":true}"#))
+                .unwrap();
+            assert_eq!(i3_read_request(&mut stream).0, I3_GET_TREE);
+            // An event sneaking in ahead of the tree
+            let early = r#"{"change":"focus","container":{"app_id":"early"}}"#;
+            stream
+                .write_all(&i3_message(I3_EVENT_WINDOW, early))
+                .unwrap();
+            let tree = r#"{"focused":false,"nodes":[{"focused":false,"nodes":[],
+                "floating_nodes":[{"focused":true,"app_id":null,
+                "window_properties":{"class":"Steam"}}]}]}"#;
+            stream.write_all(&i3_message(I3_GET_TREE, tree)).unwrap();
+            for (kind, event) in [
+                (
+                    I3_EVENT_WINDOW,
+                    r#"{"change":"title","container":{"app_id":"foot"}}"#,
+                ),
+                (
+                    I3_EVENT_WINDOW,
+                    r#"{"change":"focus","container":{"app_id":"foot"}}"#,
+                ),
+                (
+                    I3_EVENT_WORKSPACE,
+                    r#"{"change":"focus","current":{"nodes":[{}]}}"#,
+                ),
+                (
+                    I3_EVENT_WORKSPACE,
+                    r#"{"change":"focus","current":{"nodes":[]}}"#,
+                ),
+            ] {
+                stream.write_all(&i3_message(kind, event)).unwrap();
+            }
+        });
+
+        let (compositor, found) = find_socket(&dir).unwrap();
+        assert_eq!((compositor, &found), (Compositor::Sway, &path));
+        let (mut watcher, focused) = FocusWatcher::connect(compositor, &found).unwrap();
+        assert_eq!(focused, "Steam");
+        assert_eq!(watcher.next_focus().unwrap(), "foot");
+        assert_eq!(watcher.next_focus().unwrap(), "");
+        server.join().unwrap();
+        assert!(watcher.next_focus().is_err());
+    }
+
+    #[test]
+    fn follows_hyprland() {
+        let dir = TempDir::new("focus-hyprland");
+        let instance = dir.join("hypr/abcdef_1234");
+        fs::create_dir_all(&instance).unwrap();
+        let requests = UnixListener::bind(instance.join(".socket.sock")).unwrap();
+        let events = UnixListener::bind(instance.join(".socket2.sock")).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = requests.accept().unwrap();
+            let mut request = [0; 14];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request, b"j/activewindow");
+            stream
+                .write_all(br#"{"class":"kitty","title":"~"}"#)
+                .unwrap();
+            drop(stream);
+            let (mut stream, _) = events.accept().unwrap();
+            stream
+                .write_all(
+                    b"workspace>>2\nactivewindow>>firefox,Mozilla Firefox\nactivewindow>>,\n",
+                )
+                .unwrap();
+        });
+
+        let (compositor, path) = find_socket(&dir).unwrap();
+        assert_eq!(compositor, Compositor::Hyprland);
+        let (mut watcher, focused) = FocusWatcher::connect(compositor, &path).unwrap();
+        assert_eq!(focused, "kitty");
+        assert_eq!(watcher.next_focus().unwrap(), "firefox");
+        assert_eq!(watcher.next_focus().unwrap(), "");
+        server.join().unwrap();
+        assert!(watcher.next_focus().is_err());
+    }
+
+    #[test]
+    fn no_compositor() {
+        let dir = TempDir::new("focus-none");
+        fs::write(dir.join("wayland-1"), "").unwrap();
+        assert_eq!(find_socket(&dir), None);
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let mut msg = I3_MAGIC.to_vec();
+        msg.extend_from_slice(&u32::MAX.to_ne_bytes());
+        msg.extend_from_slice(&I3_GET_TREE.to_ne_bytes());
+        let err = i3_receive(&mut Cursor::new(msg)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(i3_receive(&mut Cursor::new(b"not-i3 at all!".to_vec())).is_err());
+    }
+
+    #[test]
+    fn matches_apps() {
+        assert!(app_matches("firefox", "firefox"));
+        assert!(!app_matches("firefox", "firefox-esr"));
+        assert!(!app_matches("firefox", ""));
+        assert!(app_matches("*", ""));
+        assert!(app_matches("*", "anything"));
+        assert!(app_matches("org.mozilla.*", "org.mozilla.firefox"));
+        assert!(!app_matches("org.mozilla.*", "org.gnome.Nautilus"));
+        assert!(app_matches("*code*", "com.visualstudio.code.oss"));
+        assert!(app_matches("*.Terminal", "org.gnome.Terminal"));
+        assert!(!app_matches("*.Terminal", "org.gnome.Terminal2"));
+        assert!(app_matches("a*b*c", "abc"));
+        assert!(app_matches("a*b*c", "aXbYbZc"));
+        assert!(!app_matches("a*b*c", "acb"));
+        assert!(app_matches("é*ü", "éxü"));
+    }
+}
@@ -1,5 +1,5 @@
 #![allow(non_upper_case_globals)]
-use std::ffi::{CStr, CString, c_char, c_int};
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
 use std::ptr;
 
 #[repr(C)]
@@ -10,6 +10,12 @@ struct FcPattern {
 struct FcConfig {
     _data: [u8; 0],
 }
+#[repr(C)]
+struct FcFontSet {
+    nfont: c_int,
+    _sfont: c_int,
+    fonts: *const *const FcPattern,
+}
 
 type FcResult = c_int;
 const FcResultMatch: FcResult = 0;
@@ -23,6 +29,7 @@ const FcMatchPattern: FcMatchKind = 0;
 
 pub enum FontConfigError {
     FontNotFound,
+    InvalidPattern,
 }
 
 pub struct FontConfig {
@@ -42,6 +49,38 @@ impl FontConfig {
         }
         Ok(Pattern { pattern: match_ })
     }
+    // Families of the fonts that best cover the pattern, most preferred first
+    pub fn sort_families(&self, pattern: &Pattern) -> Vec<String> {
+        let name = CString::new("family").unwrap();
+        let mut families = Vec::new();
+        unsafe {
+            let mut result: FcResult = 0;
+            // Trimming drops fonts that add no coverage over the ones before them
+            let set = FcFontSort(
+                self.config,
+                pattern.pattern,
+                1,
+                ptr::null_mut(),
+                &mut result,
+            );
+            if set.is_null() {
+                return families;
+            }
+            for i in 0..(*set).nfont as usize {
+                let mut family = ptr::null();
+                let res = FcPatternGetString(*(*set).fonts.add(i), name.as_ptr(), 0, &mut family);
+                if res != FcResultMatch {
+                    continue;
+                }
+                let family = CStr::from_ptr(family).to_string_lossy().into_owned();
+                if !families.contains(&family) {
+                    families.push(family);
+                }
+            }
+            FcFontSetDestroy(set);
+        }
+        families
+    }
     pub fn perform_substitutions(&self, pattern: &mut Pattern) {
         unsafe {
             if (FcConfigSubstitute(self.config, pattern.pattern, FcMatchPattern)) == 0 {
@@ -84,27 +123,60 @@ pub struct Pattern {
 }
 
 impl Pattern {
-    pub fn new(st: &str) -> Pattern {
-        let cstr = CString::new(st).unwrap();
+    pub fn new(st: &str) -> Result<Pattern, FontConfigError> {
+        let cstr = CString::new(st).map_err(|_| FontConfigError::InvalidPattern)?;
         let pattern = unsafe { FcNameParse(cstr.as_ptr()) };
-        Pattern { pattern }
+        if pattern.is_null() {
+            return Err(FontConfigError::InvalidPattern);
+        }
+        Ok(Pattern { pattern })
+    }
+    pub fn get_family(&self) -> &str {
+        let name = CString::new("family").unwrap();
+        unsafe {
+            let mut family = ptr::null();
+            let res = FcPatternGetString(self.pattern, name.as_ptr(), 0, &mut family);
+            throw_on_fcpattern_result(res);
+            CStr::from_ptr(family).to_str().unwrap()
+        }
     }
-    pub fn get_file_name(&self) -> &str {
-        let name = CString::new("file").unwrap();
+    // Every family in the pattern, in order of preference
+    pub fn get_families(&self) -> Vec<String> {
+        let name = CString::new("family").unwrap();
+        let mut families = Vec::new();
         unsafe {
-            let mut file_name = ptr::null();
-            let res = FcPatternGetString(self.pattern, name.as_ptr(), 0, &mut file_name);
+            loop {
+                let mut family = ptr::null();
+                let res = FcPatternGetString(
+                    self.pattern,
+                    name.as_ptr(),
+                    families.len() as c_int,
+                    &mut family,
+                );
+                if res != FcResultMatch {
+                    return families;
+                }
+                families.push(CStr::from_ptr(family).to_string_lossy().into_owned());
+            }
+        }
+    }
+    // On the OpenType scale (400 regular, 700 bold) that pango uses
+    pub fn get_weight(&self) -> i32 {
+        let name = CString::new("weight").unwrap();
+        unsafe {
+            let mut weight = 0;
+            let res = FcPatternGetInteger(self.pattern, name.as_ptr(), 0, &mut weight);
             throw_on_fcpattern_result(res);
-            CStr::from_ptr(file_name).to_str().unwrap()
+            FcWeightToOpenType(weight)
         }
     }
-    pub fn get_font_index(&self) -> isize {
-        let name = CString::new("index").unwrap();
+    pub fn get_slant(&self) -> i32 {
+        let name = CString::new("slant").unwrap();
         unsafe {
-            let mut index = 0;
-            let res = FcPatternGetInteger(self.pattern, name.as_ptr(), 0, &mut index);
+            let mut slant = 0;
+            let res = FcPatternGetInteger(self.pattern, name.as_ptr(), 0, &mut slant);
             throw_on_fcpattern_result(res);
-            index as isize
+            slant
         }
     }
 }
@@ -135,4 +207,13 @@ unsafe extern "C" {
     ) -> FcResult;
     fn FcConfigSubstitute(_: *const FcConfig, _: *const FcPattern, _: FcMatchKind) -> c_int;
     fn FcDefaultSubstitute(_: *const FcPattern);
+    fn FcWeightToOpenType(_: c_int) -> c_int;
+    fn FcFontSort(
+        _: *const FcConfig,
+        _: *const FcPattern,
+        _: c_int,
+        _: *mut c_void,
+        _: *mut FcResult,
+    ) -> *mut FcFontSet;
+    fn FcFontSetDestroy(_: *mut FcFontSet);
 }
@@ -1,20 +1,103 @@
 use crate::{
+    action::{Action, Repeat, RepeatConfig},
+    animation::Tween,
     config::{ButtonConfig, Config},
-    constants::{BUTTON_COLOR_ACTIVE, BUTTON_COLOR_INACTIVE, BUTTON_SPACING_PX},
+    layout::LayoutMetrics,
     pixel_shift::PIXEL_SHIFT_WIDTH_PX,
+    scroll::Scroll,
+    text::{FontCache, TextStyle},
+    theme::{Color, Theme},
     widgets::{TWidget, new_widget_from_config},
 };
-use cairo::{Context, Surface};
+use cairo::{Context, LinearGradient, Surface};
 use drm::control::ClipRect;
+use std::time::Duration;
+
+const FAILURE_FLASH: Duration = Duration::from_millis(600);
+
+pub struct Button {
+    // Index of the first virtual button slot taken up by this button
+    pub start: usize,
+    pub widget: Box<dyn TWidget>,
+    pub theme: Theme,
+    pub text_style: TextStyle,
+    // Key for gestures bound to Secondary
+    pub secondary: Option<Action>,
+    // Whether holding the button repeats its key
+    pub repeat: Option<Repeat>,
+    // 0.0 is the idle fill, 1.0 the pressed fill
+    pub highlight: Tween,
+    pub ripple: Option<Tween>,
+    // Shown when a command run by the button fails
+    pub failure: Option<Tween>,
+    was_active: bool,
+    // The last frame was drawn mid animation, so the final one is still owed
+    mid_animation: bool,
+}
+
+impl Button {
+    fn tweens_running(&self) -> bool {
+        self.highlight.running()
+            || self.ripple.is_some_and(|r| r.running())
+            || self.failure.is_some_and(|f| f.running())
+    }
+    fn animating(&self) -> bool {
+        self.mid_animation || self.tweens_running()
+    }
+}
+
+// draw box with rounded corners
+fn rounded_rect(c: &Context, left_edge: f64, width: f64, bot: f64, top: f64, radius: f64) {
+    c.new_sub_path();
+    let left = left_edge + radius;
+    let right = (left_edge + width) - radius;
+    c.arc(
+        right,
+        bot,
+        radius,
+        (-90.0f64).to_radians(),
+        (0.0f64).to_radians(),
+    );
+    c.arc(
+        right,
+        top,
+        radius,
+        (0.0f64).to_radians(),
+        (90.0f64).to_radians(),
+    );
+    c.arc(
+        left,
+        top,
+        radius,
+        (90.0f64).to_radians(),
+        (180.0f64).to_radians(),
+    );
+    c.arc(
+        left,
+        bot,
+        radius,
+        (180.0f64).to_radians(),
+        (270.0f64).to_radians(),
+    );
+    c.close_path();
+}
 
 #[derive(Default)]
 pub struct FunctionLayer {
-    pub buttons: Vec<(usize, Box<dyn TWidget>)>,
+    pub buttons: Vec<Button>,
     pub virtual_button_count: usize,
+    pub scroll: Scroll,
+    // Scroll offset of the last drawn frame
+    drawn_offset: f64,
 }
 
 impl FunctionLayer {
-    pub fn with_config(cfg: Vec<ButtonConfig>) -> FunctionLayer {
+    pub fn with_config(
+        cfg: Vec<ButtonConfig>,
+        layout: &LayoutMetrics,
+        theme: &Theme,
+        fonts: &mut FontCache,
+    ) -> FunctionLayer {
         if cfg.is_empty() {
             panic!("Invalid configuration, layer has 0 buttons");
         }
@@ -31,12 +114,54 @@ impl FunctionLayer {
                         stretch = 1;
                     }
                     **state += stretch;
-                    Some((i, new_widget_from_config(cfg)))
+                    let theme = match &cfg.style {
+                        Some(style) => theme.with_overrides(style),
+                        None => *theme,
+                    };
+                    let text_style = TextStyle {
+                        font: fonts.get(cfg.font.as_deref(), cfg.font_size),
+                        wrap: cfg.wrap.unwrap_or(false),
+                    };
+                    let secondary = cfg.secondary_action.clone();
+                    let repeat = cfg.repeat.and_then(RepeatConfig::resolve);
+                    if repeat.is_some() && cfg.action.repeat_key().is_none() {
+                        panic!("Invalid configuration, only keys and chords can repeat");
+                    }
+                    Some(Button {
+                        start: i,
+                        widget: new_widget_from_config(cfg, layout),
+                        theme,
+                        text_style,
+                        secondary,
+                        repeat,
+                        highlight: Tween::settled(0.0),
+                        ripple: None,
+                        failure: None,
+                        was_active: false,
+                        mid_animation: false,
+                    })
                 })
                 .collect(),
             virtual_button_count,
+            scroll: Scroll::default(),
+            drawn_offset: 0.0,
         }
     }
+    // Buttons share the width evenly, unless that makes them too narrow
+    fn virtual_button_width(&self, layout: &LayoutMetrics, width: f64) -> f64 {
+        let spacing = layout.button_spacing;
+        let fit = (width - (spacing * (self.virtual_button_count - 1) as f64))
+            / self.virtual_button_count as f64;
+        fit.max(layout.min_button_width)
+    }
+    // How far the layer can be scrolled when it is the given width
+    pub fn max_scroll(&self, layout: &LayoutMetrics, width: f64) -> f64 {
+        let spacing = layout.button_spacing;
+        let content_width = self.virtual_button_count as f64
+            * (self.virtual_button_width(layout, width) + spacing)
+            - spacing;
+        (content_width - width).max(0.0).ceil()
+    }
     pub fn draw(
         &mut self,
         config: &Config,
@@ -47,65 +172,104 @@ impl FunctionLayer {
         complete_redraw: bool,
     ) -> Vec<ClipRect> {
         let c = Context::new(surface).unwrap();
-        let mut modified_regions = if complete_redraw {
-            vec![ClipRect::new(0, 0, height as u16, width as u16)]
-        } else {
-            Vec::new()
-        };
-        c.translate(height as f64, 0.0);
-        c.rotate((90.0f64).to_radians());
+        let orientation = &config.orientation;
+        c.transform(orientation.matrix(width as f64, height as f64));
         let pixel_shift_width = if config.enable_pixel_shift {
             PIXEL_SHIFT_WIDTH_PX
         } else {
             0
         };
-        let virtual_button_width = ((width - pixel_shift_width as i32)
-            - (BUTTON_SPACING_PX * (self.virtual_button_count - 1) as i32))
-            as f64
-            / self.virtual_button_count as f64;
-        let radius = 8.0f64;
-        let bot = (height as f64) * 0.15;
-        let top = (height as f64) * 0.85;
+        let layout = &config.layout;
+        let spacing = layout.button_spacing;
+        let visible_width = (width - pixel_shift_width as i32) as f64;
+        let virtual_button_width = self.virtual_button_width(layout, visible_width);
+        let max_scroll = self.max_scroll(layout, visible_width);
+        self.scroll.step(max_scroll);
+        let scroll_offset = self.scroll.offset.round();
+        // Buttons under the edge fades can't be redrawn on their own
+        let complete_redraw =
+            complete_redraw || max_scroll > 0.0 || scroll_offset != self.drawn_offset;
+        self.drawn_offset = scroll_offset;
+        let mut modified_regions = if complete_redraw {
+            vec![orientation.clip(
+                width as f64,
+                height as f64,
+                0.0,
+                0.0,
+                width as f64,
+                height as f64,
+            )]
+        } else {
+            Vec::new()
+        };
+        let bot = layout.vertical_padding;
+        let top = height as f64 - layout.vertical_padding;
         let (pixel_shift_x, pixel_shift_y) = pixel_shift;
 
         if complete_redraw {
-            c.set_source_rgb(0.0, 0.0, 0.0);
+            config.theme.background.set_source(&c);
             c.paint().unwrap();
         }
-        c.set_font_face(&config.font_face);
-        c.set_font_size(32.0);
 
         for i in 0..self.buttons.len() {
             let end = if i + 1 < self.buttons.len() {
-                self.buttons[i + 1].0
+                self.buttons[i + 1].start
             } else {
                 self.virtual_button_count
             };
-            let (start, button) = &mut self.buttons[i];
+            let animating = self.buttons[i].animating();
+            let Button {
+                start,
+                widget: button,
+                theme,
+                text_style,
+                secondary: _,
+                repeat: _,
+                highlight,
+                ripple,
+                failure,
+                was_active,
+                mid_animation: _,
+            } = &mut self.buttons[i];
             let start = *start;
+            let radius = theme.corner_radius;
+
+            let active = button.active();
+            if active != *was_active {
+                *was_active = active;
+                let duration = config.animation.duration;
+                highlight.retarget(if active { 1.0 } else { 0.0 }, duration);
+                if active && config.animation.press_ripple && !duration.is_zero() {
+                    *ripple = Some(Tween::new(0.0, 1.0, duration * 2));
+                }
+            }
 
-            if !button.changed() && !complete_redraw {
+            if !button.changed() && !complete_redraw && !animating {
                 continue;
             };
 
-            let left_edge = (start as f64 * (virtual_button_width + BUTTON_SPACING_PX as f64))
-                .floor()
+            let left_edge = (start as f64 * (virtual_button_width + spacing)).floor()
+                - scroll_offset
                 + pixel_shift_x
                 + (pixel_shift_width / 2) as f64;
 
             let button_width = virtual_button_width
-                + ((end - start - 1) as f64 * (virtual_button_width + BUTTON_SPACING_PX as f64))
-                    .floor();
+                + ((end - start - 1) as f64 * (virtual_button_width + spacing)).floor();
 
-            let color = if button.active() {
-                BUTTON_COLOR_ACTIVE
-            } else if config.show_button_outlines {
-                BUTTON_COLOR_INACTIVE
+            // Scrolled out of view
+            if left_edge + button_width < 0.0 || left_edge > width as f64 {
+                button.reset_changed();
+                continue;
+            }
+
+            let idle_fill = if config.show_button_outlines {
+                theme.button_fill
             } else {
-                0.0
+                theme.background
             };
+            let color = idle_fill.mix(&theme.pressed_fill, highlight.value());
             if !complete_redraw {
-                c.set_source_rgb(0.0, 0.0, 0.0);
+                config.theme.background.set_source(&c);
                 c.rectangle(
                     left_edge,
                     bot - radius,
@@ -114,45 +278,66 @@ impl FunctionLayer {
                 );
                 c.fill().unwrap();
             }
-            c.set_source_rgb(color, color, color);
-            // draw box with rounded corners
-            c.new_sub_path();
-            let left = left_edge + radius;
-            let right = (left_edge + button_width.ceil()) - radius;
-            c.arc(
-                right,
-                bot,
-                radius,
-                (-90.0f64).to_radians(),
-                (0.0f64).to_radians(),
-            );
-            c.arc(
-                right,
-                top,
-                radius,
-                (0.0f64).to_radians(),
-                (90.0f64).to_radians(),
-            );
-            c.arc(
-                left,
-                top,
-                radius,
-                (90.0f64).to_radians(),
-                (180.0f64).to_radians(),
-            );
-            c.arc(
-                left,
-                bot,
-                radius,
-                (180.0f64).to_radians(),
-                (270.0f64).to_radians(),
-            );
-            c.close_path();
-
+            color.set_source(&c);
+            rounded_rect(&c, left_edge, button_width.ceil(), bot, top, radius);
             c.fill().unwrap();
-            c.set_source_rgb(1.0, 1.0, 1.0);
+
+            if let Some(r) = ripple {
+                if r.running() {
+                    // Circle growing from the middle of the button while fading out
+                    let t = r.value();
+                    let (w, h) = (button_width, top - bot + radius * 2.0);
+                    c.save().unwrap();
+                    rounded_rect(&c, left_edge, button_width.ceil(), bot, top, radius);
+                    c.clip();
+                    Color {
+                        a: theme.text.a * 0.3 * (1.0 - t),
+                        ..theme.text
+                    }
+                    .set_source(&c);
+                    c.arc(
+                        left_edge + w / 2.0,
+                        height as f64 / 2.0,
+                        t * (w * w + h * h).sqrt() / 2.0,
+                        0.0,
+                        std::f64::consts::TAU,
+                    );
+                    c.fill().unwrap();
+                    c.restore().unwrap();
+                } else {
+                    *ripple = None;
+                }
+            }
+
+            if let Some(f) = failure {
+                if f.running() {
+                    Color {
+                        a: theme.warning.a * 0.8 * f.value(),
+                        ..theme.warning
+                    }
+                    .set_source(&c);
+                    rounded_rect(&c, left_edge, button_width.ceil(), bot, top, radius);
+                    c.fill().unwrap();
+                } else {
+                    *failure = None;
+                }
+            }
+
+            if config.show_button_outlines && theme.outline_width > 0.0 {
+                rounded_rect(&c, left_edge, button_width.ceil(), bot, top, radius);
+                theme.outline.set_source(&c);
+                c.set_line_width(theme.outline_width);
+                c.stroke().unwrap();
+            }
+            let pressed_text = theme.pressed_text.unwrap_or(theme.text);
+            theme
+                .text
+                .mix(&pressed_text, highlight.value())
+                .set_source(&c);
             button.render(
                 &c,
+                theme,
+                text_style,
                 height,
                 left_edge,
                 button_width.ceil() as u64,
@@ -160,30 +345,104 @@ impl FunctionLayer {
             );
 
             button.reset_changed();
+            let still_running = self.buttons[i].tweens_running();
+            self.buttons[i].mid_animation = still_running;
 
             if !complete_redraw {
-                modified_regions.push(ClipRect::new(
-                    height as u16 - top as u16 - radius as u16,
-                    left_edge as u16,
-                    height as u16 - bot as u16 + radius as u16,
-                    left_edge as u16 + button_width as u16,
+                modified_regions.push(orientation.clip(
+                    width as f64,
+                    height as f64,
+                    left_edge,
+                    bot - radius,
+                    button_width,
+                    top - bot + radius * 2.0,
                 ));
             }
         }
 
+        if max_scroll > 0.0 {
+            // Fade out the edges that have more buttons beyond them
+            let fade_width = height as f64 / 2.0;
+            let bg = config.theme.background;
+            for (visible, edge, inner) in [
+                (scroll_offset > 0.0, 0.0, fade_width),
+                (
+                    scroll_offset < max_scroll,
+                    width as f64,
+                    width as f64 - fade_width,
+                ),
+            ] {
+                if !visible {
+                    continue;
+                }
+                let fade = LinearGradient::new(edge, 0.0, inner, 0.0);
+                fade.add_color_stop_rgba(0.0, bg.r, bg.g, bg.b, bg.a);
+                fade.add_color_stop_rgba(1.0, bg.r, bg.g, bg.b, 0.0);
+                c.set_source(&fade).unwrap();
+                c.rectangle(edge.min(inner), 0.0, fade_width, height as f64);
+                c.fill().unwrap();
+            }
+        }
+
         modified_regions
     }
 
-    pub fn hit(&self, width: u16, height: u16, x: f64, y: f64, i: Option<usize>) -> Option<usize> {
-        let virtual_button_width =
-            (width as i32 - (BUTTON_SPACING_PX * (self.virtual_button_count - 1) as i32)) as f64
-                / self.virtual_button_count as f64;
+    // Flash the buttons that run a command that just failed
+    pub fn show_failure(&mut self, argv: &[String]) {
+        for button in &mut self.buttons {
+            if button.widget.get_action().flashes_on_failure(argv) {
+                button.failure = Some(Tween::new(1.0, 0.0, FAILURE_FLASH));
+            }
+        }
+    }
+
+    // Whether any button still has an animation in flight
+    pub fn animating(&self) -> bool {
+        self.scroll.running() || self.buttons.iter().any(Button::animating)
+    }
+
+    // Left edge and width of a button in touch coordinates
+    fn touch_span(&self, config: &Config, width: u16, i: usize) -> (f64, f64) {
+        let spacing = config.layout.button_spacing;
+        let virtual_button_width = self.virtual_button_width(&config.layout, width as f64);
+
+        let start = self.buttons[i].start;
+        let end = if i + 1 < self.buttons.len() {
+            self.buttons[i + 1].start
+        } else {
+            self.virtual_button_count
+        };
+
+        let left_edge =
+            (start as f64 * (virtual_button_width + spacing)).floor() - self.scroll.offset.round();
+
+        let button_width = virtual_button_width
+            + ((end - start - 1) as f64 * (virtual_button_width + spacing)).floor();
+        (left_edge, button_width)
+    }
+
+    pub fn hit(
+        &self,
+        config: &Config,
+        width: u16,
+        height: u16,
+        x: f64,
+        y: f64,
+        i: Option<usize>,
+    ) -> Option<usize> {
+        let (x, y) = config
+            .orientation
+            .touch_to_logical(x, y, width as f64, height as f64);
+        // Touches are accepted a bit above and below the drawn button
+        let touch_padding = config.layout.vertical_padding * 2.0 / 3.0;
 
         let i = i.unwrap_or_else(|| {
-            let virtual_i = (x / (width as f64 / self.virtual_button_count as f64)) as usize;
+            let pitch = self.virtual_button_width(&config.layout, width as f64)
+                + config.layout.button_spacing;
+            let virtual_i = ((x + self.scroll.offset.round()) / pitch) as usize;
             self.buttons
                 .iter()
-                .position(|(start, _)| *start > virtual_i)
+                .position(|b| b.start > virtual_i)
                 .unwrap_or(self.buttons.len())
                 - 1
         });
@@ -191,27 +450,33 @@ impl FunctionLayer {
             return None;
         }
 
-        let start = self.buttons[i].0;
-        let end = if i + 1 < self.buttons.len() {
-            self.buttons[i + 1].0
-        } else {
-            self.virtual_button_count
-        };
-
-        let left_edge = (start as f64 * (virtual_button_width + BUTTON_SPACING_PX as f64)).floor();
-
-        let button_width = virtual_button_width
-            + ((end - start - 1) as f64 * (virtual_button_width + BUTTON_SPACING_PX as f64))
-                .floor();
+        let (left_edge, button_width) = self.touch_span(config, width, i);
 
         if x < left_edge
             || x > (left_edge + button_width)
-            || y < 0.1 * height as f64
-            || y > 0.9 * height as f64
+            || y < touch_padding
+            || y > height as f64 - touch_padding
         {
             return None;
         }
 
         Some(i)
     }
+
+    // How far across button i a touch is, 0.0 at its left edge and 1.0 at the right
+    pub fn position(
+        &self,
+        config: &Config,
+        width: u16,
+        height: u16,
+        x: f64,
+        y: f64,
+        i: usize,
+    ) -> f64 {
+        let (x, _) = config
+            .orientation
+            .touch_to_logical(x, y, width as f64, height as f64);
+        let (left_edge, button_width) = self.touch_span(config, width, i);
+        ((x - left_edge) / button_width).clamp(0.0, 1.0)
+    }
 }
@@ -0,0 +1,380 @@
+use crate::action::Action;
+use input_linux::Key;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// Fingers moving less than this still count as tapping or holding
+const TAP_SLOP_PX: f64 = 24.0;
+const TAP_TIME: Duration = Duration::from_millis(250);
+const LONG_PRESS_TIME: Duration = Duration::from_millis(500);
+// Longest gap between the two taps of a double tap, and how far apart they may be
+const DOUBLE_TAP_GAP: Duration = Duration::from_millis(300);
+const DOUBLE_TAP_DISTANCE_PX: f64 = 48.0;
+const SWIPE_DISTANCE_PX: f64 = 150.0;
+const SWIPE_TIME: Duration = Duration::from_millis(400);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Gesture {
+    SwipeLeft,
+    SwipeRight,
+    LongPress,
+    DoubleTap,
+    TwoFingerTap,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum GestureAction {
+    // Flip between the primary and Fn layers
+    SwitchLayer,
+    Key(Action),
+    // The SecondaryAction of the button the gesture was made on
+    Secondary,
+}
+
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct GestureBindings {
+    pub swipe_left: Option<GestureAction>,
+    pub swipe_right: Option<GestureAction>,
+    pub long_press: Option<GestureAction>,
+    pub double_tap: Option<GestureAction>,
+    pub two_finger_tap: Option<GestureAction>,
+}
+
+impl GestureBindings {
+    pub fn get(&self, gesture: Gesture) -> Option<&GestureAction> {
+        match gesture {
+            Gesture::SwipeLeft => self.swipe_left.as_ref(),
+            Gesture::SwipeRight => self.swipe_right.as_ref(),
+            Gesture::LongPress => self.long_press.as_ref(),
+            Gesture::DoubleTap => self.double_tap.as_ref(),
+            Gesture::TwoFingerTap => self.two_finger_tap.as_ref(),
+        }
+    }
+    // Gestures that can start anywhere on the bar, so every touch has to wait
+    // a moment before pressing its button
+    pub fn layer_wide(&self) -> bool {
+        self.swipe_left.is_some() || self.swipe_right.is_some() || self.two_finger_tap.is_some()
+    }
+    // Gestures made on buttons with a secondary action
+    pub fn per_button(&self) -> bool {
+        self.long_press.is_some() || self.double_tap.is_some()
+    }
+    // Actions bound with Key
+    pub fn actions(&self) -> impl Iterator<Item = &Action> {
+        [
+            &self.swipe_left,
+            &self.swipe_right,
+            &self.long_press,
+            &self.double_tap,
+            &self.two_finger_tap,
+        ]
+        .into_iter()
+        .filter_map(|action| match action {
+            Some(GestureAction::Key(action)) => Some(action),
+            _ => None,
+        })
+    }
+    pub fn keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.actions().flat_map(Action::keys)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Recognized {
+    pub gesture: Gesture,
+    // The touch that completed the gesture
+    pub slot: u32,
+}
+
+struct Track {
+    down_at: Instant,
+    start_x: f64,
+    x: f64,
+    moved: bool,
+    long_pressed: bool,
+}
+
+// Turns a stream of touches in logical coordinates into gestures. Time is passed in
+// rather than read from the clock, so touch sequences can be replayed.
+#[derive(Default)]
+pub struct GestureRecognizer {
+    touches: HashMap<u32, Track>,
+    // Since the bar was last untouched
+    sequence_start: Option<Instant>,
+    max_fingers: usize,
+    sequence_moved: bool,
+    last_tap: Option<(Instant, f64)>,
+}
+
+impl GestureRecognizer {
+    pub fn down(&mut self, slot: u32, x: f64, time: Instant) {
+        if self.touches.is_empty() {
+            self.sequence_start = Some(time);
+            self.max_fingers = 0;
+            self.sequence_moved = false;
+        }
+        self.touches.insert(
+            slot,
+            Track {
+                down_at: time,
+                start_x: x,
+                x,
+                moved: false,
+                long_pressed: false,
+            },
+        );
+        self.max_fingers = self.max_fingers.max(self.touches.len());
+    }
+    pub fn motion(&mut self, slot: u32, x: f64) {
+        if let Some(track) = self.touches.get_mut(&slot) {
+            track.x = x;
+            if (x - track.start_x).abs() > TAP_SLOP_PX {
+                track.moved = true;
+                self.sequence_moved = true;
+            }
+        }
+    }
+    pub fn up(&mut self, slot: u32, time: Instant) -> Option<Recognized> {
+        let track = self.touches.remove(&slot)?;
+        let recognized = |gesture| Some(Recognized { gesture, slot });
+        if self.max_fingers == 2 {
+            let started = self.sequence_start.unwrap_or(track.down_at);
+            if self.touches.is_empty() && !self.sequence_moved && time - started <= TAP_TIME {
+                return recognized(Gesture::TwoFingerTap);
+            }
+            return None;
+        }
+        if self.max_fingers > 1 || track.long_pressed {
+            return None;
+        }
+        let held = time - track.down_at;
+        let dx = track.x - track.start_x;
+        if dx.abs() >= SWIPE_DISTANCE_PX && held <= SWIPE_TIME {
+            self.last_tap = None;
+            return recognized(if dx < 0.0 {
+                Gesture::SwipeLeft
+            } else {
+                Gesture::SwipeRight
+            });
+        }
+        if track.moved || held > TAP_TIME {
+            self.last_tap = None;
+            return None;
+        }
+        match self.last_tap.take() {
+            Some((tapped_at, x))
+                if time - tapped_at <= DOUBLE_TAP_GAP
+                    && (x - track.x).abs() <= DOUBLE_TAP_DISTANCE_PX =>
+            {
+                recognized(Gesture::DoubleTap)
+            }
+            _ => {
+                self.last_tap = Some((time, track.x));
+                None
+            }
+        }
+    }
+    // Long presses are recognised while the finger is still down
+    pub fn tick(&mut self, time: Instant) -> Vec<Recognized> {
+        if self.max_fingers != 1 {
+            return Vec::new();
+        }
+        let mut recognized = Vec::new();
+        for (&slot, track) in self.touches.iter_mut() {
+            if !track.moved && !track.long_pressed && time - track.down_at >= LONG_PRESS_TIME {
+                track.long_pressed = true;
+                recognized.push(Recognized {
+                    gesture: Gesture::LongPress,
+                    slot,
+                });
+            }
+        }
+        recognized
+    }
+    // When tick should next be called
+    pub fn deadline(&self) -> Option<Instant> {
+        if self.max_fingers != 1 {
+            return None;
+        }
+        self.touches
+            .values()
+            .filter(|t| !t.moved && !t.long_pressed)
+            .map(|t| t.down_at + LONG_PRESS_TIME)
+            .min()
+    }
+    // Forget all touches, for when the layers they were on go away
+    pub fn reset(&mut self) {
+        *self = GestureRecognizer::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    // A finger put down at x and lifted at x + dx after the given time
+    fn stroke(
+        gestures: &mut GestureRecognizer,
+        slot: u32,
+        x: f64,
+        dx: f64,
+        start: Instant,
+        held: Duration,
+    ) -> Option<Recognized> {
+        gestures.down(slot, x, start);
+        gestures.motion(slot, x + dx / 2.0);
+        gestures.motion(slot, x + dx);
+        gestures.up(slot, start + held)
+    }
+
+    fn gesture(recognized: Option<Recognized>) -> Option<Gesture> {
+        recognized.map(|r| r.gesture)
+    }
+
+    #[test]
+    fn swipes() {
+        let mut gestures = GestureRecognizer::default();
+        let t = Instant::now();
+        let left = stroke(&mut gestures, 0, 1000.0, -200.0, t, 200 * MS);
+        assert_eq!(
+            left,
+            Some(Recognized {
+                gesture: Gesture::SwipeLeft,
+                slot: 0
+            })
+        );
+        let right = stroke(&mut gestures, 1, 1000.0, 200.0, t + 500 * MS, 200 * MS);
+        assert_eq!(gesture(right), Some(Gesture::SwipeRight));
+        // Too short, or too slow
+        assert_eq!(stroke(&mut gestures, 0, 1000.0, 100.0, t, 200 * MS), None);
+        assert_eq!(stroke(&mut gestures, 0, 1000.0, 200.0, t, 600 * MS), None);
+    }
+
+    #[test]
+    fn long_press() {
+        let mut gestures = GestureRecognizer::default();
+        let t = Instant::now();
+        gestures.down(3, 500.0, t);
+        assert_eq!(gestures.deadline(), Some(t + LONG_PRESS_TIME));
+        assert!(gestures.tick(t + 400 * MS).is_empty());
+        assert_eq!(
+            gestures.tick(t + LONG_PRESS_TIME),
+            vec![Recognized {
+                gesture: Gesture::LongPress,
+                slot: 3
+            }]
+        );
+        // Only once, and the release isn't also a tap
+        assert!(gestures.tick(t + 900 * MS).is_empty());
+        assert_eq!(gestures.deadline(), None);
+        assert_eq!(gestures.up(3, t + 1000 * MS), None);
+    }
+
+    #[test]
+    fn moving_finger_is_no_long_press() {
+        let mut gestures = GestureRecognizer::default();
+        let t = Instant::now();
+        gestures.down(0, 500.0, t);
+        gestures.motion(0, 500.0 + TAP_SLOP_PX + 1.0);
+        assert!(gestures.tick(t + LONG_PRESS_TIME).is_empty());
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut gestures = GestureRecognizer::default();
+        let t = Instant::now();
+        assert_eq!(stroke(&mut gestures, 0, 500.0, 0.0, t, 50 * MS), None);
+        let second = stroke(&mut gestures, 0, 510.0, 0.0, t + 200 * MS, 50 * MS);
+        assert_eq!(gesture(second), Some(Gesture::DoubleTap));
+        // A third tap starts over
+        assert_eq!(
+            stroke(&mut gestures, 0, 510.0, 0.0, t + 400 * MS, 50 * MS),
+            None
+        );
+    }
+
+    #[test]
+    fn double_tap_too_slow_or_too_far() {
+        let mut gestures = GestureRecognizer::default();
+        let t = Instant::now();
+        let gap = DOUBLE_TAP_GAP + 50 * MS;
+        assert_eq!(stroke(&mut gestures, 0, 500.0, 0.0, t, 50 * MS), None);
+        assert_eq!(stroke(&mut gestures, 0, 500.0, 0.0, t + gap, 50 * MS), None);
+
+        let t = t + 2000 * MS;
+        let far = 500.0 + DOUBLE_TAP_DISTANCE_PX + 1.0;
+        assert_eq!(stroke(&mut gestures, 0, 500.0, 0.0, t, 50 * MS), None);
+        assert_eq!(
+            stroke(&mut gestures, 0, far, 0.0, t + 100 * MS, 50 * MS),
+            None
+        );
+    }
+
+    #[test]
+    fn slop_cancels_tap() {
+        let mut gestures = GestureRecognizer::default();
+        let t = Instant::now();
+        assert_eq!(stroke(&mut gestures, 0, 500.0, 0.0, t, 50 * MS), None);
+        // Still close enough for a double tap, but it wandered too far to be a tap
+        let moved = stroke(
+            &mut gestures,
+            0,
+            500.0,
+            TAP_SLOP_PX + 1.0,
+            t + 100 * MS,
+            50 * MS,
+        );
+        assert_eq!(moved, None);
+        // Within the slop it still counts
+        assert_eq!(
+            stroke(&mut gestures, 0, 500.0, 0.0, t + 300 * MS, 50 * MS),
+            None
+        );
+        let wobbly = stroke(&mut gestures, 0, 500.0, TAP_SLOP_PX, t + 400 * MS, 50 * MS);
+        assert_eq!(gesture(wobbly), Some(Gesture::DoubleTap));
+    }
+
+    #[test]
+    fn two_finger_tap() {
+        let mut gestures = GestureRecognizer::default();
+        let t = Instant::now();
+        gestures.down(0, 500.0, t);
+        gestures.down(1, 700.0, t + 20 * MS);
+        assert_eq!(gestures.deadline(), None);
+        assert_eq!(gestures.up(0, t + 100 * MS), None);
+        assert_eq!(
+            gestures.up(1, t + 120 * MS),
+            Some(Recognized {
+                gesture: Gesture::TwoFingerTap,
+                slot: 1
+            })
+        );
+
+        // Not when held too long or moved
+        gestures.down(0, 500.0, t);
+        gestures.down(1, 700.0, t);
+        gestures.up(0, t + 300 * MS);
+        assert_eq!(gestures.up(1, t + 300 * MS), None);
+        gestures.down(0, 500.0, t);
+        gestures.down(1, 700.0, t);
+        gestures.motion(1, 800.0);
+        gestures.up(0, t + 100 * MS);
+        assert_eq!(gestures.up(1, t + 100 * MS), None);
+    }
+
+    #[test]
+    fn reset_forgets_touches() {
+        let mut gestures = GestureRecognizer::default();
+        let t = Instant::now();
+        gestures.down(0, 500.0, t);
+        gestures.reset();
+        assert_eq!(gestures.up(0, t + 50 * MS), None);
+        assert!(gestures.tick(t + LONG_PRESS_TIME).is_empty());
+    }
+}
@@ -7,24 +7,24 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::{button_image::ButtonImage, constants::ICON_SIZE};
+use crate::button_image::ButtonImage;
 pub fn try_load_svg(path: &str) -> Result<ButtonImage> {
     Ok(ButtonImage::Svg(
         Handle::from_file(path)?.ok_or(anyhow!("failed to load image"))?,
     ))
 }
 
-pub fn try_load_png(path: impl AsRef<Path>) -> Result<ButtonImage> {
+pub fn try_load_png(path: impl AsRef<Path>, icon_size: i32) -> Result<ButtonImage> {
     let mut file = File::open(path)?;
     let surf = ImageSurface::create_from_png(&mut file)?;
-    if surf.height() == ICON_SIZE && surf.width() == ICON_SIZE {
+    if surf.height() == icon_size && surf.width() == icon_size {
         return Ok(ButtonImage::Bitmap(surf));
     }
-    let resized = ImageSurface::create(Format::ARgb32, ICON_SIZE, ICON_SIZE).unwrap();
+    let resized = ImageSurface::create(Format::ARgb32, icon_size, icon_size).unwrap();
     let c = Context::new(&resized).unwrap();
     c.scale(
-        ICON_SIZE as f64 / surf.width() as f64,
-        ICON_SIZE as f64 / surf.height() as f64,
+        icon_size as f64 / surf.width() as f64,
+        icon_size as f64 / surf.height() as f64,
     );
     c.set_source_surface(surf, 0.0, 0.0).unwrap();
     c.set_antialias(Antialias::Best);
@@ -35,6 +35,7 @@ pub fn try_load_png(path: impl AsRef<Path>) -> Result<ButtonImage> {
 pub fn try_load_image(
     name: impl AsRef<str>,
     theme: Option<impl AsRef<str>>,
+    icon_size: i32,
 ) -> Result<ButtonImage> {
     let name = name.as_ref();
     let locations;
@@ -47,7 +48,7 @@ pub fn try_load_image(
             lookup(name)
                 .with_cache()
                 .with_theme(theme)
-                .with_size(ICON_SIZE as u16)
+                .with_size(icon_size as u16)
                 .force_svg()
                 .find(),
             lookup(name)
@@ -74,7 +75,7 @@ pub fn try_load_image(
 
     for location in locations {
         let result = match location.extension().and_then(|s| s.to_str()) {
-            Some("png") => try_load_png(&location),
+            Some("png") => try_load_png(&location, icon_size),
             Some("svg") => try_load_svg(
                 location
                     .to_str()
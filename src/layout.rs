@@ -0,0 +1,59 @@
+use serde::Deserialize;
+
+// The default metrics were designed for the 60px high touch bar panels
+const REFERENCE_HEIGHT: f64 = 60.0;
+const BUTTON_SPACING_PX: f64 = 16.0;
+const CORNER_RADIUS_PX: f64 = 8.0;
+const VERTICAL_PADDING: f64 = 0.15; // fraction of the panel height above and below buttons
+const ICON_SIZE_PX: f64 = 48.0;
+const FONT_SIZE_PX: f64 = 32.0;
+// Layers with more buttons than fit at this width scroll sideways
+const MIN_BUTTON_WIDTH_PX: f64 = 64.0;
+
+// Optional overrides from the config, all in pixels
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct LayoutOverrides {
+    pub button_spacing: Option<f64>,
+    pub corner_radius: Option<f64>,
+    pub vertical_padding: Option<f64>,
+    pub icon_size: Option<i32>,
+    pub font_size: Option<f64>,
+    pub min_button_width: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LayoutMetrics {
+    pub button_spacing: f64,
+    pub corner_radius: f64,
+    pub vertical_padding: f64,
+    pub icon_size: i32,
+    pub font_size: f64,
+    pub min_button_width: f64,
+}
+
+impl LayoutMetrics {
+    pub fn new(height: u16, overrides: &LayoutOverrides) -> LayoutMetrics {
+        let scale = height as f64 / REFERENCE_HEIGHT;
+        LayoutMetrics {
+            button_spacing: overrides
+                .button_spacing
+                .unwrap_or((BUTTON_SPACING_PX * scale).round()),
+            corner_radius: overrides
+                .corner_radius
+                .unwrap_or((CORNER_RADIUS_PX * scale).round()),
+            vertical_padding: overrides
+                .vertical_padding
+                .unwrap_or(height as f64 * VERTICAL_PADDING),
+            icon_size: overrides
+                .icon_size
+                .unwrap_or((ICON_SIZE_PX * scale).round() as i32),
+            font_size: overrides
+                .font_size
+                .unwrap_or((FONT_SIZE_PX * scale).round()),
+            min_button_width: overrides
+                .min_button_width
+                .unwrap_or((MIN_BUTTON_WIDTH_PX * scale).round()),
+        }
+    }
+}
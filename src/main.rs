@@ -1,7 +1,6 @@
 use anyhow::Result;
 use cairo::{Format, ImageSurface};
 use constants::TIMEOUT_MS;
-use drm::control::ClipRect;
 use input::{
     Device as InputDevice, Libinput, LibinputInterface,
     event::{
@@ -24,33 +23,63 @@ use nix::{
 use privdrop::PrivDrop;
 use std::{
     collections::HashMap,
+    env,
     fs::{File, OpenOptions},
+    mem,
     os::{
         fd::{AsFd, AsRawFd},
         unix::{fs::OpenOptionsExt, io::OwnedFd},
     },
     panic::{self, AssertUnwindSafe},
     path::Path,
+    thread,
     time::Instant,
 };
-use widgets::set_widget_active;
+use widgets::{move_widget_touch, set_widget_active};
 
+mod action;
+mod animation;
 mod backlight;
 mod button_image;
+mod command;
 mod config;
 mod constants;
+mod control;
+mod crash;
 mod display;
+mod fn_key;
+mod focus;
 mod fonts;
 mod function_layer;
+mod gestures;
 mod graphics_load;
+mod layout;
 mod metrics;
+mod modifiers;
 mod pixel_shift;
+mod scroll;
+mod supervisor;
+#[cfg(test)]
+mod temp_dir;
+mod text;
+mod theme;
 mod widgets;
 
-use crate::config::ConfigManager;
+use crate::config::{Config, ConfigManager, DisplayConfig, load_display_config};
+use action::{Action, LayerAction, VirtualKeyboard};
+use animation::LayerTransition;
 use backlight::BacklightManager;
-use display::DrmBackend;
+use command::CommandRunner;
+use control::{ButtonState, ControlServer, LayerState, Response};
+use crash::{show_crash_screen, take_panic_summary};
+use display::{DisplayBackend, DrmBackend, DrmHotplug, HeadlessBackend, HotplugEvent};
+use fn_key::{FnKey, FnMode, draw_latch_indicator};
+use function_layer::FunctionLayer;
+use gestures::{Gesture, GestureAction, GestureRecognizer};
+use modifiers::ModifierTracker;
 use pixel_shift::PixelShiftManager;
+use scroll::{PAN_THRESHOLD_PX, Pan, PendingTouch};
+use supervisor::Supervisor;
 
 struct Interface;
 
@@ -88,41 +117,98 @@ where
         .unwrap();
 }
 
+const VIRTUAL_DEVICE_NAME: &str = "Dynamic Function Row Virtual Input Device";
+
+// Size of the 13" Intel MacBook Pro touch bar, used when rendering previews and
+// for laying out the layers until the display shows up
+const PREVIEW_SIZE: (u16, u16) = (2170, 60);
+
 fn main() {
-    let mut drm = DrmBackend::open_card().unwrap();
-    let (height, width) = drm.mode().size();
-    // Run real main and catch panic's so we can show crash message on dfr
-    let _ = panic::catch_unwind(AssertUnwindSafe(|| real_main(&mut drm)));
-    let crash_bitmap = include_bytes!("crash_bitmap.raw");
-    let mut map = drm.map().unwrap();
-    let data = map.as_mut();
-    let mut wptr = 0;
-    for byte in crash_bitmap {
-        for i in 0..8 {
-            let bit = ((byte >> i) & 0x1) == 0;
-            let color = if bit { 0xFF } else { 0x0 };
-            data[wptr] = color;
-            data[wptr + 1] = color;
-            data[wptr + 2] = color;
-            data[wptr + 3] = color;
-            wptr += 4;
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("--preview") => {
+            let out_dir = args
+                .next()
+                .expect("Usage: tiny-dfr --preview <output directory>");
+            render_preview(Path::new(&out_dir));
+            return;
         }
+        // Started by the command helper, as the CommandUser
+        Some("--follow-focus") => {
+            command::run_focus_watcher();
+            return;
+        }
+        _ => {}
     }
-    drop(map);
-    drm.dirty(&[ClipRect::new(0, 0, height, width)]).unwrap();
+    crash::install_panic_hook();
+    // A broken config is reported from real_main, so it shows up on the crash screen
+    let display_cfg = panic::catch_unwind(load_display_config).unwrap_or_default();
+    // Without the display the main loop waits for it to be plugged in
+    let mut drm: Option<Box<dyn DisplayBackend>> =
+        match DrmBackend::open_card(display_cfg.min_aspect_ratio) {
+            Ok(display) => Some(Box::new(display)),
+            Err(err) => {
+                println!("{err}");
+                println!("Waiting for the touch bar display to show up");
+                None
+            }
+        };
+    // Run real main and catch panic's so we can show crash message on dfr
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| supervise(&mut drm, &display_cfg)));
     let mut sigset = SigSet::empty();
     sigset.add(Signal::SIGTERM);
+    // The display may have gone away while we were running
+    let Some(drm) = drm.as_mut() else {
+        sigset.wait().unwrap();
+        return;
+    };
+    let summary = take_panic_summary().unwrap_or_else(|| "unknown error".to_string());
+    show_crash_screen(drm.as_mut(), &display_cfg.orientation, &summary).unwrap();
     sigset.wait().unwrap();
 }
 
-fn real_main(drm: &mut DrmBackend) {
-    let (height, width) = drm.mode().size();
-    let (db_width, db_height) = drm.fb_info().unwrap().size();
-    let mut uinput = UInputHandle::new(OpenOptions::new().write(true).open("/dev/uinput").unwrap());
-    let mut backlight = BacklightManager::new();
-    let mut cfg_mgr = ConfigManager::new();
-    let (mut cfg, mut layers) = cfg_mgr.load_config(width);
-    let mut pixel_shift = PixelShiftManager::new();
+// Everything that needs root to open, kept around across restarts of the main loop
+struct Daemon {
+    hotplug: DrmHotplug,
+    keyboard: VirtualKeyboard,
+    // Missing until the touch bar backlight shows up
+    backlight: Option<BacklightManager>,
+    cfg_mgr: ConfigManager,
+    // Missing if the socket couldn't be created
+    control: Option<ControlServer>,
+    // Keys registered on the virtual keyboard, missing while it does not exist
+    keys: Option<Vec<Key>>,
+    // Size of the display the layers were last laid out for
+    mode_size: (u16, u16),
+}
+
+impl Daemon {
+    // Let go of any key a touch was holding down and remove the virtual keyboard
+    fn release_keys(&mut self) {
+        let Some(keys) = self.keys.take() else {
+            return;
+        };
+        self.keyboard.release_all(&keys);
+        self.keyboard.uinput.dev_destroy().unwrap();
+    }
+}
+
+fn supervise(drm: &mut Option<Box<dyn DisplayBackend>>, display_cfg: &DisplayConfig) {
+    let mode_size = match drm.as_ref() {
+        Some(display) => display.mode_size(),
+        None => display_cfg.orientation.mode_size(PREVIEW_SIZE),
+    };
+    // Forked first, so it inherits as little as possible
+    let commands = CommandRunner::spawn().unwrap();
+    let hotplug = DrmHotplug::new().unwrap();
+    let uinput = UInputHandle::new(OpenOptions::new().write(true).open("/dev/uinput").unwrap());
+    let backlight = BacklightManager::new()
+        .map_err(|err| println!("{err}, waiting for the touch bar backlight to show up"))
+        .ok();
+    let cfg_mgr = ConfigManager::new();
+    let control = ControlServer::bind()
+        .map_err(|err| println!("Failed to create the control socket: {err}"))
+        .ok();
 
     // drop privileges to input and video group
     let groups = ["input", "video"];
@@ -133,33 +219,121 @@ fn real_main(drm: &mut DrmBackend) {
         .apply()
         .unwrap_or_else(|e| panic!("Failed to drop privileges: {}", e));
 
+    let daemon = Daemon {
+        hotplug,
+        keyboard: VirtualKeyboard::new(uinput, commands),
+        backlight,
+        cfg_mgr,
+        control,
+        keys: None,
+        mode_size,
+    };
+    Supervisor::new().run(
+        &mut (drm, daemon),
+        |(drm, daemon)| real_main(drm, daemon, display_cfg),
+        |(drm, daemon), summary| {
+            daemon.release_keys();
+            daemon.cfg_mgr.fall_back();
+            if let Some(display) = drm.as_mut()
+                && let Err(err) =
+                    show_crash_screen(display.as_mut(), &display_cfg.orientation, summary)
+            {
+                println!("Failed to show the crash screen: {err}");
+            }
+        },
+        thread::sleep,
+    );
+}
+
+// Render every layer of the current config into PNG files, no touch bar required
+fn render_preview(out_dir: &Path) {
+    let orientation = load_display_config().orientation;
+    let mut display = HeadlessBackend::new(orientation.mode_size(PREVIEW_SIZE));
+    let (width, height) = PREVIEW_SIZE;
+    let (db_width, db_height) = display.buffer_size();
+    let (cfg, mut layers) = ConfigManager::new().load_config(display.mode_size());
     let mut surface =
         ImageSurface::create(Format::ARgb32, db_width as i32, db_height as i32).unwrap();
-    let mut active_layer = 0;
-    let mut needs_complete_redraw = true;
+    for (i, layer) in layers.iter_mut().enumerate() {
+        let clips = layer.draw(
+            &cfg,
+            width as i32,
+            height as i32,
+            &surface,
+            (0.0, 0.0),
+            true,
+        );
+        display.present(&surface.data().unwrap(), &clips).unwrap();
+        let path = out_dir.join(format!("layer-{i}.png"));
+        display.write_png(&path, &cfg.orientation).unwrap();
+        println!("Wrote {}", path.display());
+    }
+}
 
-    let mut input_tb = Libinput::new_with_udev(Interface);
-    let mut input_main = Libinput::new_with_udev(Interface);
-    input_tb.udev_assign_seat("seat-touchbar").unwrap();
-    input_main.udev_assign_seat("seat0").unwrap();
-    let epoll = Epoll::new(EpollCreateFlags::empty()).unwrap();
-    epoll
-        .add(input_main.as_fd(), EpollEvent::new(EpollFlags::EPOLLIN, 0))
-        .unwrap();
-    epoll
-        .add(input_tb.as_fd(), EpollEvent::new(EpollFlags::EPOLLIN, 1))
-        .unwrap();
-    epoll
-        .add(cfg_mgr.fd(), EpollEvent::new(EpollFlags::EPOLLIN, 2))
-        .unwrap();
-    uinput.set_evbit(EventKind::Key).unwrap();
-    for layer in &layers {
-        for button in &layer.buttons {
-            uinput.set_keybit(button.1.get_action()).unwrap();
+// Drop the display, making sure its fd no longer wakes up the main loop
+fn close_display(epoll: &Epoll, drm: &mut Option<Box<dyn DisplayBackend>>) {
+    if let Some(fd) = drm.as_ref().and_then(|d| d.fd()) {
+        let _ = epoll.delete(fd);
+    }
+    *drm = None;
+}
+
+// Start animating over to another layer, returns false if it is already shown
+fn switch_layer(
+    active_layer: &mut usize,
+    new_layer: usize,
+    transition: &mut Option<LayerTransition>,
+    surface: &ImageSurface,
+    cfg: &Config,
+) -> bool {
+    if *active_layer == new_layer {
+        return false;
+    }
+    let direction = if new_layer > *active_layer { 1.0 } else { -1.0 };
+    *transition = LayerTransition::new(surface, &cfg.animation, direction);
+    *active_layer = new_layer;
+    true
+}
+
+// Modifier layers show while their modifiers are held, on top of everything else.
+// Below them layers opened by buttons cover the primary, Fn and app layers until closed.
+fn shown_layer(
+    cfg: &Config,
+    modifiers: &ModifierTracker,
+    pushed: &[usize],
+    fn_key: &FnKey,
+    base_layer: usize,
+) -> usize {
+    cfg.modifier_layer(&modifiers.held())
+        .or(pushed.last().copied())
+        .unwrap_or_else(|| fn_key.layer(base_layer))
+}
+
+// Every key a button or gesture can send, the virtual keyboard has to announce them up front
+fn layer_keys(cfg: &Config, layers: &[FunctionLayer]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let button_keys = layers
+        .iter()
+        .flat_map(|layer| &layer.buttons)
+        .flat_map(|button| {
+            let secondary = button.secondary.iter().flat_map(Action::keys);
+            button.widget.keys().into_iter().chain(secondary)
+        });
+    for key in button_keys.chain(cfg.gestures.keys()) {
+        if key != Key::Reserved && !keys.contains(&key) {
+            keys.push(key);
         }
     }
+    keys
+}
+
+fn create_keyboard(uinput: &UInputHandle<File>, keys: &[Key]) {
+    uinput.set_evbit(EventKind::Key).unwrap();
+    for &key in keys {
+        uinput.set_keybit(key).unwrap();
+    }
     let mut dev_name_c = [0 as c_char; 80];
-    let dev_name = "Dynamic Function Row Virtual Input Device".as_bytes();
+    let dev_name = VIRTUAL_DEVICE_NAME.as_bytes();
     for i in 0..dev_name.len() {
         dev_name_c[i] = dev_name[i] as c_char;
     }
@@ -176,20 +350,264 @@ fn real_main(drm: &mut DrmBackend) {
         })
         .unwrap();
     uinput.dev_create().unwrap();
+}
+
+fn real_main(
+    drm: &mut Option<Box<dyn DisplayBackend>>,
+    daemon: &mut Daemon,
+    display_cfg: &DisplayConfig,
+) {
+    let Daemon {
+        hotplug,
+        keyboard,
+        backlight,
+        cfg_mgr,
+        control,
+        keys,
+        mode_size,
+    } = daemon;
+    // A previous run may have failed while the display was unplugged,
+    // the surface gets resized once it comes back
+    let (db_width, db_height) = match drm.as_ref() {
+        Some(display) => display.buffer_size(),
+        None => (mode_size.0 as u32, mode_size.1 as u32),
+    };
+    let (mut cfg, mut layers) = cfg_mgr.load_config(*mode_size);
+    keyboard
+        .commands
+        .set_user(cfg.command_user.clone(), !cfg.app_layers.is_empty());
+    let (mut width, mut height) = cfg.orientation.logical_size(*mode_size);
+    let mut pixel_shift = PixelShiftManager::new();
+
+    let mut surface =
+        ImageSurface::create(Format::ARgb32, db_width as i32, db_height as i32).unwrap();
+    // Shown while Fn isn't held, the primary layer or one for the focused app
+    let mut base_layer = cfg.base_layer(keyboard.commands.focused_app());
+    let mut fn_key = FnKey::new(cfg.fn_mode, cfg.fn_layer_default);
+    let mut pushed: Vec<usize> = Vec::new();
+    let mut modifiers = ModifierTracker::default();
+    let mut active_layer = fn_key.layer(base_layer);
+    let mut needs_complete_redraw = true;
+    let mut transition: Option<LayerTransition> = None;
+
+    let mut input_tb = Libinput::new_with_udev(Interface);
+    let mut input_main = Libinput::new_with_udev(Interface);
+    input_tb.udev_assign_seat("seat-touchbar").unwrap();
+    input_main.udev_assign_seat("seat0").unwrap();
+    let epoll = Epoll::new(EpollCreateFlags::empty()).unwrap();
+    epoll
+        .add(input_main.as_fd(), EpollEvent::new(EpollFlags::EPOLLIN, 0))
+        .unwrap();
+    epoll
+        .add(input_tb.as_fd(), EpollEvent::new(EpollFlags::EPOLLIN, 1))
+        .unwrap();
+    epoll
+        .add(cfg_mgr.fd(), EpollEvent::new(EpollFlags::EPOLLIN, 2))
+        .unwrap();
+    if let Some(fd) = drm.as_ref().and_then(|d| d.fd()) {
+        epoll
+            .add(fd, EpollEvent::new(EpollFlags::EPOLLIN, 3))
+            .unwrap();
+    }
+    epoll
+        .add(hotplug.fd(), EpollEvent::new(EpollFlags::EPOLLIN, 4))
+        .unwrap();
+    epoll
+        .add(
+            keyboard.commands.fd(),
+            EpollEvent::new(EpollFlags::EPOLLIN, 5),
+        )
+        .unwrap();
+    if let Some(control) = control.as_mut() {
+        control.disconnect_all();
+        epoll
+            .add(control.fd(), EpollEvent::new(EpollFlags::EPOLLIN, 6))
+            .unwrap();
+    }
+    let new_keys = layer_keys(&cfg, &layers);
+    create_keyboard(&keyboard.uinput, &new_keys);
+    let keys = keys.insert(new_keys);
 
     let mut digitizer: Option<InputDevice> = None;
     let mut touches = HashMap::new();
+    let mut pending: HashMap<u32, PendingTouch> = HashMap::new();
+    let mut pan: Option<Pan> = None;
+    let mut recognizer = GestureRecognizer::default();
+    // Recognised gestures and the button they were made on
+    let mut fired: Vec<(Gesture, Option<(usize, usize)>)> = Vec::new();
+    let mut reload_requested = false;
     loop {
-        if cfg_mgr.update_config(&mut cfg, &mut layers, width) {
-            active_layer = 0;
+        let mut reloaded = if mem::take(&mut reload_requested) {
+            cfg_mgr.reload(&mut cfg, &mut layers, *mode_size);
+            true
+        } else {
+            cfg_mgr.update_config(&mut cfg, &mut layers, *mode_size)
+        };
+
+        let mut display_added = false;
+        for event in hotplug.events() {
+            match event {
+                HotplugEvent::Added => display_added = true,
+                HotplugEvent::Removed(path) => {
+                    if drm.as_ref().and_then(|d| d.devnode()) == Some(path.as_path()) {
+                        println!("Touch bar display {} went away", path.display());
+                        close_display(&epoll, drm);
+                    }
+                }
+            }
+        }
+        let retry =
+            (drm.is_none() || backlight.is_none()) && (display_added || hotplug.should_retry());
+        if backlight.is_none() && retry {
+            match BacklightManager::new() {
+                Ok(manager) => {
+                    println!("Touch bar backlight is back");
+                    *backlight = Some(manager);
+                }
+                Err(err) if display_added => println!("{err}"),
+                Err(_) => {}
+            }
+        }
+        if drm.is_none() && retry {
+            match DrmBackend::open_card(display_cfg.min_aspect_ratio) {
+                Ok(display) => {
+                    println!("Touch bar display is back");
+                    if let Some(fd) = display.fd() {
+                        epoll
+                            .add(fd, EpollEvent::new(EpollFlags::EPOLLIN, 3))
+                            .unwrap();
+                    }
+                    // Layers are laid out for a specific width, so only rebuild them if it changed
+                    if display.mode_size() != *mode_size {
+                        *mode_size = display.mode_size();
+                        (cfg, layers) = cfg_mgr.load_config(*mode_size);
+                        reloaded = true;
+                    }
+                    let (db_width, db_height) = display.buffer_size();
+                    if (surface.width() as u32, surface.height() as u32) != (db_width, db_height) {
+                        surface =
+                            ImageSurface::create(Format::ARgb32, db_width as i32, db_height as i32)
+                                .unwrap();
+                    }
+                    *drm = Some(Box::new(display));
+                    transition = None;
+                    needs_complete_redraw = true;
+                }
+                Err(err) if display_added => println!("{err}"),
+                Err(_) => {}
+            }
+        }
+        // Both a config reload and a display with a new size leave us with new layers
+        if reloaded {
+            keyboard
+                .commands
+                .set_user(cfg.command_user.clone(), !cfg.app_layers.is_empty());
+            (width, height) = cfg.orientation.logical_size(*mode_size);
+            base_layer = cfg.base_layer(keyboard.commands.focused_app());
+            fn_key.configure(cfg.fn_mode, cfg.fn_layer_default);
+            pushed.clear();
+            active_layer = shown_layer(&cfg, &modifiers, &pushed, &fn_key, base_layer);
+            // The buttons being held are gone, and so is anything that pointed at them
+            keyboard.release_all(keys);
+            // Keys the old layers didn't use can't be sent until the device is set up again
+            let new_keys = layer_keys(&cfg, &layers);
+            if new_keys != *keys {
+                keyboard.uinput.dev_destroy().unwrap();
+                *keys = new_keys;
+                create_keyboard(&keyboard.uinput, keys);
+            }
+            touches.clear();
+            pending.clear();
+            pan = None;
+            recognizer.reset();
+            transition = None;
             needs_complete_redraw = true;
         }
 
+        // Carry on with macros waiting out a delay
+        keyboard.run();
+        for action in keyboard.take_layer_actions() {
+            match action {
+                LayerAction::Push(name) => pushed.extend(cfg.layer_index(&name)),
+                LayerAction::Pop => {
+                    pushed.pop();
+                }
+                LayerAction::Switch(name) => {
+                    pushed.pop();
+                    pushed.extend(cfg.layer_index(&name));
+                }
+            }
+            needs_complete_redraw |= switch_layer(
+                &mut active_layer,
+                shown_layer(&cfg, &modifiers, &pushed, &fn_key, base_layer),
+                &mut transition,
+                &surface,
+                &cfg,
+            );
+        }
+
+        // Touches that stayed put long enough press their button after all
+        let now = Instant::now();
+        pending.retain(|&slot, touch| {
+            if touch.press_time().is_none_or(|t| t > now) {
+                return true;
+            }
+            let button = &mut layers[touch.layer].buttons[touch.btn];
+            set_widget_active(&mut button.widget, button.repeat, keyboard, true);
+            touches.insert(slot, (touch.layer, touch.btn));
+            false
+        });
+        for recognized in recognizer.tick(now) {
+            if cfg.gestures.get(recognized.gesture).is_none() {
+                continue;
+            }
+            // Held touches wait for this, their button is never pressed
+            if let Some(touch) = pending.remove(&recognized.slot) {
+                fired.push((recognized.gesture, Some((touch.layer, touch.btn))));
+            }
+        }
+        for (gesture, button) in fired.drain(..) {
+            let Some(action) = cfg.gestures.get(gesture) else {
+                continue;
+            };
+            // Swipes on layers that scroll are taken by the scrolling
+            if matches!(gesture, Gesture::SwipeLeft | Gesture::SwipeRight)
+                && layers[active_layer].max_scroll(&cfg.layout, width as f64) > 0.0
+            {
+                continue;
+            }
+            match action {
+                GestureAction::SwitchLayer => {
+                    fn_key.toggle_latch();
+                    pushed.clear();
+                    switch_layer(
+                        &mut active_layer,
+                        shown_layer(&cfg, &modifiers, &pushed, &fn_key, base_layer),
+                        &mut transition,
+                        &surface,
+                        &cfg,
+                    );
+                    needs_complete_redraw = true;
+                }
+                GestureAction::Key(action) => keyboard.send(action.tap()),
+                GestureAction::Secondary => {
+                    if let Some(action) =
+                        button.and_then(|(l, b)| layers[l].buttons[b].secondary.as_ref())
+                    {
+                        keyboard.send(action.tap());
+                    }
+                }
+            }
+        }
+
         // Walk all widgets in current layer; and find which one needs a re-draw soonest
         let mut next_redraw_time = layers[active_layer]
             .buttons
             .iter()
-            .filter_map(|(_, w)| w.next_draw_time())
+            .filter_map(|b| b.widget.next_draw_time())
+            .chain(pending.values().filter_map(PendingTouch::press_time))
+            .chain(recognizer.deadline())
+            .chain(keyboard.deadline())
             .min()
             .unwrap_or(Instant::now() + TIMEOUT_MS);
 
@@ -203,39 +621,242 @@ fn real_main(drm: &mut DrmBackend) {
             }
         }
 
-        if needs_complete_redraw || layers[active_layer].buttons.iter().any(|b| b.1.changed()) {
+        let animating = drm.is_some() && (transition.is_some() || layers[active_layer].animating());
+        if let Some(frame_time) = animation::next_draw_time(animating, Instant::now()) {
+            next_redraw_time = next_redraw_time.min(frame_time);
+        }
+
+        // While a page flip is in flight the frame is drawn once it completes
+        if let Some(display) = drm.as_mut()
+            && display.ready()
+            && (needs_complete_redraw
+                || animating
+                || layers[active_layer]
+                    .buttons
+                    .iter()
+                    .any(|b| b.widget.changed()))
+        {
             let shift = if cfg.enable_pixel_shift {
                 pixel_shift.get()
             } else {
                 (0.0, 0.0)
             };
-            let clips = layers[active_layer].draw(
+            // The layer being transitioned to is drawn in full and blended in every frame
+            let mut clips = layers[active_layer].draw(
                 &cfg,
                 width as i32,
                 height as i32,
                 &surface,
                 shift,
-                needs_complete_redraw,
+                needs_complete_redraw || transition.is_some(),
             );
-            let data = surface.data().unwrap();
-            drm.map().unwrap().as_mut()[..data.len()].copy_from_slice(&data);
-            drm.dirty(&clips).unwrap();
+            if fn_key.latched() {
+                clips.push(draw_latch_indicator(
+                    &cfg,
+                    width as i32,
+                    height as i32,
+                    &surface,
+                ));
+            }
+            let presented = match transition.as_mut() {
+                Some(t) => {
+                    let frame = t.composite(
+                        &surface,
+                        cfg.theme.background,
+                        &cfg.orientation,
+                        width as f64,
+                        height as f64,
+                    );
+                    display.present(&frame.data().unwrap(), &clips)
+                }
+                None => display.present(&surface.data().unwrap(), &clips),
+            };
+            if let Err(err) = presented {
+                println!("Failed to present frame, dropping display: {err}");
+                close_display(&epoll, drm);
+            }
             needs_complete_redraw = false;
+            if transition.as_ref().is_some_and(|t| !t.running()) {
+                // Finish on an unblended frame of the new layer
+                transition = None;
+                needs_complete_redraw = true;
+            }
         }
 
-        let wait_time = next_redraw_time.saturating_duration_since(Instant::now());
+        let wake_time = if drm.is_none() || backlight.is_none() {
+            next_redraw_time.min(hotplug.next_retry_time())
+        } else {
+            next_redraw_time
+        };
+        let wait_time = match drm {
+            Some(display) if !display.ready() => TIMEOUT_MS,
+            _ => wake_time.saturating_duration_since(Instant::now()),
+        };
 
-        match epoll.wait(
-            &mut [EpollEvent::new(EpollFlags::EPOLLIN, 0)],
-            wait_time.as_millis() as u16,
-        ) {
-            Err(Errno::EINTR) | Ok(_) => 0,
+        let mut events = [EpollEvent::empty(); 8];
+        let n_events = match epoll.wait(&mut events, wait_time.as_millis() as u16) {
+            Err(Errno::EINTR) => 0,
             e => e.unwrap(),
         };
+        if events[..n_events].iter().any(|e| e.data() == 3)
+            && let Some(display) = drm.as_mut()
+            && let Err(err) = display.process_events()
+        {
+            println!("Lost the display: {err}");
+            close_display(&epoll, drm);
+        }
+        let requests = match control.as_mut() {
+            Some(control) => control.requests(&epoll),
+            None => Vec::new(),
+        };
+        for (client, command) in requests {
+            let response = match command {
+                // Like a SwitchLayer action
+                control::Command::SwitchLayer { layer } => {
+                    match layer.resolve(&cfg, layers.len()) {
+                        Some(index) => {
+                            pushed.pop();
+                            pushed.push(index);
+                            needs_complete_redraw |= switch_layer(
+                                &mut active_layer,
+                                index,
+                                &mut transition,
+                                &surface,
+                                &cfg,
+                            );
+                            Response::ok()
+                        }
+                        None => Response::error(format!("there is no layer {layer}")),
+                    }
+                }
+                control::Command::SetText {
+                    layer,
+                    button,
+                    text,
+                } => match layer
+                    .resolve(&cfg, layers.len())
+                    .and_then(|l| layers[l].buttons.get_mut(button))
+                {
+                    Some(b) => {
+                        if b.widget.set_text(&text) {
+                            Response::ok()
+                        } else {
+                            Response::error("this button has no text")
+                        }
+                    }
+                    None => Response::error(format!("there is no button {layer}:{button}")),
+                },
+                control::Command::SetIcon {
+                    layer,
+                    button,
+                    icon,
+                    theme,
+                } => match layer
+                    .resolve(&cfg, layers.len())
+                    .and_then(|l| layers[l].buttons.get_mut(button))
+                {
+                    Some(b) => match b.widget.set_icon(&icon, theme.as_deref()) {
+                        Ok(true) => Response::ok(),
+                        Ok(false) => Response::error("this button has no icon"),
+                        Err(err) => Response::error(format!("failed to load {icon}: {err}")),
+                    },
+                    None => Response::error(format!("there is no button {layer}:{button}")),
+                },
+                control::Command::State => Response {
+                    state: Some(control::State {
+                        active_layer,
+                        fn_mode: fn_key.mode.name().to_string(),
+                        fn_layer_default: fn_key.fn_layer_default,
+                        fn_latched: fn_key.latched(),
+                        display_connected: drm.is_some(),
+                        layers: layers
+                            .iter()
+                            .enumerate()
+                            .map(|(i, layer)| LayerState {
+                                name: cfg
+                                    .layer_indices
+                                    .iter()
+                                    .find(|&(_, &index)| index == i)
+                                    .map(|(name, _)| name.clone()),
+                                buttons: layer
+                                    .buttons
+                                    .iter()
+                                    .map(|b| ButtonState {
+                                        text: b.widget.text().map(str::to_owned),
+                                        active: b.widget.active(),
+                                    })
+                                    .collect(),
+                            })
+                            .collect(),
+                    }),
+                    ..Response::ok()
+                },
+                control::Command::SetFnMode {
+                    mode,
+                    fn_layer_default,
+                } => match FnMode::parse(&mode) {
+                    Some(mode) => {
+                        let fn_layer_default = fn_layer_default.unwrap_or(fn_key.fn_layer_default);
+                        fn_key.configure(mode, fn_layer_default);
+                        switch_layer(
+                            &mut active_layer,
+                            shown_layer(&cfg, &modifiers, &pushed, &fn_key, base_layer),
+                            &mut transition,
+                            &surface,
+                            &cfg,
+                        );
+                        needs_complete_redraw = true;
+                        Response::ok()
+                    }
+                    None => Response::error(format!("there is no Fn mode called {mode}")),
+                },
+                control::Command::Reload => {
+                    reload_requested = true;
+                    Response::ok()
+                }
+                control::Command::Screenshot => {
+                    let mut png = Vec::new();
+                    match cfg
+                        .orientation
+                        .upright(&surface, width, height)
+                        .and_then(|frame| Ok(frame.write_to_png(&mut png)?))
+                    {
+                        Ok(()) => Response {
+                            png: Some(control::hex(&png)),
+                            ..Response::ok()
+                        },
+                        Err(err) => Response::error(format!("failed to take a screenshot: {err}")),
+                    }
+                }
+            };
+            if let Some(control) = control.as_mut() {
+                control.reply(&epoll, client, &response);
+            }
+        }
+        for (argv, ok) in keyboard.commands.finished() {
+            if !ok {
+                for layer in layers.iter_mut() {
+                    layer.show_failure(&argv);
+                }
+            }
+        }
+        let focused_layer = cfg.base_layer(keyboard.commands.focused_app());
+        if focused_layer != base_layer {
+            base_layer = focused_layer;
+            needs_complete_redraw |= switch_layer(
+                &mut active_layer,
+                shown_layer(&cfg, &modifiers, &pushed, &fn_key, base_layer),
+                &mut transition,
+                &surface,
+                &cfg,
+            );
+        }
         input_tb.dispatch().unwrap();
         input_main.dispatch().unwrap();
         for event in &mut input_tb.clone().chain(input_main.clone()) {
-            backlight.process_event(&event);
+            if let Some(backlight) = backlight.as_mut() {
+                backlight.process_event(&event);
+            }
             match event {
                 Event::Device(DeviceEvent::Added(evt)) => {
                     let dev = evt.device();
@@ -243,58 +864,216 @@ fn real_main(drm: &mut DrmBackend) {
                         digitizer = Some(dev);
                     }
                 }
-                Event::Keyboard(KeyboardEvent::Key(key)) => {
-                    if key.key() == Key::Fn as u32 {
-                        let new_layer = match key.key_state() {
-                            KeyState::Pressed => 1,
-                            KeyState::Released => 0,
-                        };
-                        if active_layer != new_layer {
-                            active_layer = new_layer;
-                            needs_complete_redraw = true;
+                // A keyboard unplugged with modifiers held never releases them
+                Event::Device(DeviceEvent::Removed(evt))
+                    if modifiers.remove_device(evt.device().sysname()) =>
+                {
+                    needs_complete_redraw |= switch_layer(
+                        &mut active_layer,
+                        shown_layer(&cfg, &modifiers, &pushed, &fn_key, base_layer),
+                        &mut transition,
+                        &surface,
+                        &cfg,
+                    );
+                }
+                Event::Keyboard(KeyboardEvent::Key(key)) if key.key() == Key::Fn as u32 => {
+                    let latched = fn_key.latched();
+                    match key.key_state() {
+                        // Fn closes any layers opened by buttons
+                        KeyState::Pressed => {
+                            pushed.clear();
+                            fn_key.press(Instant::now());
                         }
+                        KeyState::Released => fn_key.release(Instant::now()),
+                    }
+                    let switched = switch_layer(
+                        &mut active_layer,
+                        shown_layer(&cfg, &modifiers, &pushed, &fn_key, base_layer),
+                        &mut transition,
+                        &surface,
+                        &cfg,
+                    );
+                    // The latch indicator comes or goes
+                    needs_complete_redraw |= switched || latched != fn_key.latched();
+                }
+                Event::Keyboard(KeyboardEvent::Key(key)) => {
+                    let pressed = key.key_state() == KeyState::Pressed;
+                    if pressed {
+                        fn_key.interrupt();
+                    }
+                    // Modifiers in chords sent by buttons don't count
+                    let device = key.device();
+                    if device.name() != VIRTUAL_DEVICE_NAME
+                        && modifiers.key(device.sysname(), key.key(), pressed)
+                    {
+                        needs_complete_redraw |= switch_layer(
+                            &mut active_layer,
+                            shown_layer(&cfg, &modifiers, &pushed, &fn_key, base_layer),
+                            &mut transition,
+                            &surface,
+                            &cfg,
+                        );
                     }
                 }
                 Event::Touch(te) => {
-                    if Some(te.device()) != digitizer || backlight.current_bl() == 0 {
+                    let dark = backlight.as_ref().is_some_and(|b| b.current_bl() == 0);
+                    if Some(te.device()) != digitizer || dark {
                         continue;
                     }
                     match te {
                         TouchEvent::Down(dn) => {
+                            fn_key.interrupt();
                             let x = dn.x_transformed(width as u32);
                             let y = dn.y_transformed(height as u32);
-                            if let Some(btn) = layers[active_layer].hit(width, height, x, y, None) {
+                            let (logical_x, _) =
+                                cfg.orientation
+                                    .touch_to_logical(x, y, width as f64, height as f64);
+                            recognizer.down(dn.seat_slot(), logical_x, Instant::now());
+                            if let Some(pan) = pan.as_mut() {
+                                pan.add_finger(dn.seat_slot(), logical_x);
+                                continue;
+                            }
+                            let scrollable =
+                                layers[active_layer].max_scroll(&cfg.layout, width as f64) > 0.0;
+                            let gestures = &cfg.gestures;
+                            // A second finger turns a touch that hasn't pressed its button yet into a pan
+                            if let Some(&slot) = pending.keys().next() {
+                                let first = pending.remove(&slot).unwrap();
+                                let mut two_finger = Pan::new(first.layer);
+                                two_finger.add_finger(slot, first.x);
+                                two_finger.add_finger(dn.seat_slot(), logical_x);
+                                pan = Some(two_finger);
+                                continue;
+                            }
+                            if let Some(btn) =
+                                layers[active_layer].hit(&cfg, width, height, x, y, None)
+                            {
+                                let button = &layers[active_layer].buttons[btn];
+                                // Buttons with gestures of their own press when the finger lifts
+                                let hold = gestures.per_button() && button.secondary.is_some();
+                                if (scrollable || gestures.layer_wide() || hold)
+                                    && !button.widget.tracks_touch()
+                                {
+                                    pending.insert(
+                                        dn.seat_slot(),
+                                        PendingTouch {
+                                            layer: active_layer,
+                                            btn,
+                                            start_x: logical_x,
+                                            x: logical_x,
+                                            down_at: Instant::now(),
+                                            hold,
+                                        },
+                                    );
+                                    continue;
+                                }
                                 touches.insert(dn.seat_slot(), (active_layer, btn));
+                                let layer = &mut layers[active_layer];
+                                let button = &mut layer.buttons[btn];
                                 set_widget_active(
-                                    &mut layers[active_layer].buttons[btn].1,
-                                    &mut uinput,
+                                    &mut button.widget,
+                                    button.repeat,
+                                    keyboard,
                                     true,
                                 );
+                                if button.widget.tracks_touch() {
+                                    let position = layer.position(&cfg, width, height, x, y, btn);
+                                    move_widget_touch(
+                                        &mut layer.buttons[btn].widget,
+                                        keyboard,
+                                        position,
+                                    );
+                                }
                             }
                         }
                         TouchEvent::Motion(mtn) => {
+                            let x = mtn.x_transformed(width as u32);
+                            let y = mtn.y_transformed(height as u32);
+                            let (logical_x, _) =
+                                cfg.orientation
+                                    .touch_to_logical(x, y, width as f64, height as f64);
+                            recognizer.motion(mtn.seat_slot(), logical_x);
+                            if let Some(touch) = pending.get_mut(&mtn.seat_slot()) {
+                                touch.x = logical_x;
+                                if (logical_x - touch.start_x).abs() >= PAN_THRESHOLD_PX {
+                                    let mut flick = Pan::new(touch.layer);
+                                    flick.add_finger(mtn.seat_slot(), touch.start_x);
+                                    pending.remove(&mtn.seat_slot());
+                                    pan = Some(flick);
+                                }
+                            }
+                            if let Some(pan) = pan.as_mut()
+                                && pan.has_finger(mtn.seat_slot())
+                            {
+                                let dx = pan.move_finger(mtn.seat_slot(), logical_x);
+                                let layer = &mut layers[pan.layer];
+                                let max_scroll = layer.max_scroll(&cfg.layout, width as f64);
+                                if max_scroll > 0.0 {
+                                    layer.scroll.scroll_by(dx, max_scroll);
+                                    needs_complete_redraw = true;
+                                }
+                                continue;
+                            }
                             if !touches.contains_key(&mtn.seat_slot()) {
                                 continue;
                             }
 
-                            let x = mtn.x_transformed(width as u32);
-                            let y = mtn.y_transformed(height as u32);
                             let (layer, btn) = *touches.get(&mtn.seat_slot()).unwrap();
+                            // Sliders keep following the finger once it leaves the button
+                            if layers[layer].buttons[btn].widget.tracks_touch() {
+                                let position =
+                                    layers[layer].position(&cfg, width, height, x, y, btn);
+                                move_widget_touch(
+                                    &mut layers[layer].buttons[btn].widget,
+                                    keyboard,
+                                    position,
+                                );
+                                continue;
+                            }
                             let hit = layers[active_layer]
-                                .hit(width, height, x, y, Some(btn))
+                                .hit(&cfg, width, height, x, y, Some(btn))
                                 .is_some();
-                            set_widget_active(&mut layers[layer].buttons[btn].1, &mut uinput, hit);
+                            let button = &mut layers[layer].buttons[btn];
+                            set_widget_active(&mut button.widget, button.repeat, keyboard, hit);
                         }
                         TouchEvent::Up(up) => {
+                            let recognized = recognizer.up(up.seat_slot(), Instant::now());
+                            match recognized.map(|r| r.gesture) {
+                                Some(gesture @ Gesture::DoubleTap) => {
+                                    // Replaces the tap, but only on buttons with a secondary action
+                                    if let Some(touch) = pending.get(&up.seat_slot())
+                                        && touch.hold
+                                        && cfg.gestures.get(gesture).is_some()
+                                    {
+                                        fired.push((gesture, Some((touch.layer, touch.btn))));
+                                        pending.remove(&up.seat_slot());
+                                    }
+                                }
+                                Some(gesture) => fired.push((gesture, None)),
+                                None => {}
+                            }
+                            if let Some(flick) = pan.as_mut()
+                                && flick.has_finger(up.seat_slot())
+                            {
+                                if flick.remove_finger(up.seat_slot()) {
+                                    layers[flick.layer].scroll.fling(flick.fling_velocity());
+                                    pan = None;
+                                }
+                                continue;
+                            }
+                            // Lifted before the press delay ran out, so it's a tap
+                            if let Some(touch) = pending.remove(&up.seat_slot()) {
+                                let widget = &mut layers[touch.layer].buttons[touch.btn].widget;
+                                set_widget_active(widget, None, keyboard, true);
+                                set_widget_active(widget, None, keyboard, false);
+                                continue;
+                            }
                             if !touches.contains_key(&up.seat_slot()) {
                                 continue;
                             }
                             let (layer, btn) = *touches.get(&up.seat_slot()).unwrap();
-                            set_widget_active(
-                                &mut layers[layer].buttons[btn].1,
-                                &mut uinput,
-                                false,
-                            );
+                            let button = &mut layers[layer].buttons[btn];
+                            set_widget_active(&mut button.widget, button.repeat, keyboard, false);
                         }
                         _ => {}
                     }
@@ -302,6 +1081,8 @@ fn real_main(drm: &mut DrmBackend) {
                 _ => {}
             }
         }
-        backlight.update_backlight(&cfg);
+        if let Some(backlight) = backlight.as_mut() {
+            backlight.update_backlight(&cfg);
+        }
     }
 }
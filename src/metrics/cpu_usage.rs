@@ -24,56 +24,29 @@ impl CPUUsage {
         let new_sample = Self::take_stats();
         let mut measurements = CPUSample::default();
         if let Some(old_reading) = &self.last_cpu
-            && let Some(new_readings) = &new_sample {
-                let ticks_passed = Self::total_time(new_readings) - Self::total_time(old_reading);
-                if ticks_passed > 0 {
-                    measurements.idle = ((new_readings.idle.saturating_sub(old_reading.idle) * 100)
-                        / ticks_passed) as u8;
-                    measurements.nice = ((new_readings.nice.saturating_sub(old_reading.nice) * 100)
-                        / ticks_passed) as u8;
-                    measurements.system = ((new_readings.system.saturating_sub(old_reading.system)
-                        * 100)
-                        / ticks_passed) as u8;
-                    measurements.user = ((new_readings.user.saturating_sub(old_reading.user) * 100)
-                        / ticks_passed) as u8;
-                    measurements.guest = ((new_readings
-                        .guest
-                        .unwrap_or_default()
-                        .saturating_sub(old_reading.guest.unwrap_or_default())
-                        * 100)
-                        / ticks_passed) as u8;
-                    measurements.guest_nice = ((new_readings
-                        .guest_nice
-                        .unwrap_or_default()
-                        .saturating_sub(old_reading.guest_nice.unwrap_or_default())
-                        * 100)
-                        / ticks_passed) as u8;
-                    measurements.iowait = ((new_readings
-                        .iowait
-                        .unwrap_or_default()
-                        .saturating_sub(old_reading.iowait.unwrap_or_default())
-                        * 100)
-                        / ticks_passed) as u8;
-                    measurements.irq = ((new_readings
-                        .irq
-                        .unwrap_or_default()
-                        .saturating_sub(old_reading.irq.unwrap_or_default())
-                        * 100)
-                        / ticks_passed) as u8;
-                    measurements.softirq = ((new_readings
-                        .softirq
-                        .unwrap_or_default()
-                        .saturating_sub(old_reading.softirq.unwrap_or_default())
-                        * 100)
-                        / ticks_passed) as u8;
-                    measurements.steal = ((new_readings
-                        .steal
-                        .unwrap_or_default()
-                        .saturating_sub(old_reading.steal.unwrap_or_default())
-                        * 100)
-                        / ticks_passed) as u8;
-                }
-            }
+            && let Some(new_readings) = &new_sample
+        {
+            let ticks_passed = Self::total_time(new_readings) - Self::total_time(old_reading);
+            // Share of the ticks since the last sample, 0 if no time has passed
+            let percent = |new: u64, old: u64| {
+                (new.saturating_sub(old) * 100)
+                    .checked_div(ticks_passed)
+                    .unwrap_or(0) as Percent
+            };
+            let optional = |new: Option<u64>, old: Option<u64>| {
+                percent(new.unwrap_or_default(), old.unwrap_or_default())
+            };
+            measurements.idle = percent(new_readings.idle, old_reading.idle);
+            measurements.nice = percent(new_readings.nice, old_reading.nice);
+            measurements.system = percent(new_readings.system, old_reading.system);
+            measurements.user = percent(new_readings.user, old_reading.user);
+            measurements.guest = optional(new_readings.guest, old_reading.guest);
+            measurements.guest_nice = optional(new_readings.guest_nice, old_reading.guest_nice);
+            measurements.iowait = optional(new_readings.iowait, old_reading.iowait);
+            measurements.irq = optional(new_readings.irq, old_reading.irq);
+            measurements.softirq = optional(new_readings.softirq, old_reading.softirq);
+            measurements.steal = optional(new_readings.steal, old_reading.steal);
+        }
         self.last_cpu = new_sample;
         measurements
     }
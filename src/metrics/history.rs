@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+// No touch bar is wider than this in pixels, so more samples could never be told apart
+pub const MAX_HISTORY: usize = 2170;
+
+// The most recent samples of a metric, oldest first
+pub struct History {
+    samples: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        let capacity = capacity.clamp(2, MAX_HISTORY);
+        History {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+    pub fn push(&mut self, sample: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+    pub fn latest(&self) -> Option<f64> {
+        self.samples.back().copied()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = f64> + '_ {
+        self.samples.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_oldest_first() {
+        let mut history = History::new(3);
+        for sample in [0.1, 0.2, 0.3, 0.4, 0.5] {
+            history.push(sample);
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.iter().collect::<Vec<_>>(), [0.3, 0.4, 0.5]);
+    }
+
+    #[test]
+    fn latest_is_newest_sample() {
+        let mut history = History::new(3);
+        assert_eq!(history.latest(), None);
+        history.push(0.7);
+        assert_eq!(history.latest(), Some(0.7));
+        for sample in [0.1, 0.2, 0.3] {
+            history.push(sample);
+        }
+        assert_eq!(history.latest(), Some(0.3));
+    }
+
+    #[test]
+    fn capacity_is_clamped() {
+        assert_eq!(History::new(0).capacity(), 2);
+        assert_eq!(History::new(1).capacity(), 2);
+        assert_eq!(History::new(usize::MAX).capacity(), MAX_HISTORY);
+        let mut history = History::new(1);
+        history.push(0.1);
+        history.push(0.2);
+        assert_eq!(history.iter().collect::<Vec<_>>(), [0.1, 0.2]);
+    }
+}
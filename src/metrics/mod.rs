@@ -1,6 +1,8 @@
 mod cpu_usage;
+mod history;
 mod memory_usage;
 
 pub use self::cpu_usage::*;
+pub use self::history::*;
 pub use self::memory_usage::*;
 type Percent = u8;
@@ -0,0 +1,131 @@
+use input_linux::Key;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Super,
+}
+
+impl Modifier {
+    // Left and right keys count the same
+    pub fn from_key(code: u32) -> Option<Modifier> {
+        match Key::from_code(code as u16).ok()? {
+            Key::LeftCtrl | Key::RightCtrl => Some(Modifier::Ctrl),
+            Key::LeftAlt | Key::RightAlt => Some(Modifier::Alt),
+            Key::LeftShift | Key::RightShift => Some(Modifier::Shift),
+            Key::LeftMeta | Key::RightMeta => Some(Modifier::Super),
+            _ => None,
+        }
+    }
+}
+
+// Modifier keys held on every keyboard, by device, so a keyboard going away
+// doesn't leave its keys stuck
+#[derive(Default)]
+pub struct ModifierTracker {
+    held: HashMap<String, Vec<u32>>,
+}
+
+impl ModifierTracker {
+    // Returns whether the set of held modifiers changed
+    pub fn key(&mut self, device: &str, code: u32, pressed: bool) -> bool {
+        if Modifier::from_key(code).is_none() {
+            return false;
+        }
+        let before = self.held();
+        let keys = self.held.entry(device.to_string()).or_default();
+        if pressed {
+            if !keys.contains(&code) {
+                keys.push(code);
+            }
+        } else {
+            keys.retain(|&k| k != code);
+        }
+        self.held() != before
+    }
+    // Returns whether the set of held modifiers changed
+    pub fn remove_device(&mut self, device: &str) -> bool {
+        let before = self.held();
+        self.held.remove(device);
+        self.held() != before
+    }
+    // Sorted, without duplicates
+    pub fn held(&self) -> Vec<Modifier> {
+        let mut held: Vec<Modifier> = self
+            .held
+            .values()
+            .flatten()
+            .filter_map(|&code| Modifier::from_key(code))
+            .collect();
+        held.sort();
+        held.dedup();
+        held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT_CTRL: u32 = Key::LeftCtrl as u32;
+    const RIGHT_CTRL: u32 = Key::RightCtrl as u32;
+    const LEFT_SHIFT: u32 = Key::LeftShift as u32;
+
+    #[test]
+    fn ignores_other_keys() {
+        let mut tracker = ModifierTracker::default();
+        assert!(!tracker.key("kbd", Key::A as u32, true));
+        assert_eq!(tracker.held(), []);
+        assert_eq!(
+            Modifier::from_key(Key::RightMeta as u32),
+            Some(Modifier::Super)
+        );
+    }
+
+    #[test]
+    fn left_and_right_are_the_same() {
+        let mut tracker = ModifierTracker::default();
+        assert!(tracker.key("kbd", LEFT_CTRL, true));
+        assert!(!tracker.key("kbd", RIGHT_CTRL, true));
+        assert_eq!(tracker.held(), [Modifier::Ctrl]);
+        // Still held with the other key
+        assert!(!tracker.key("kbd", LEFT_CTRL, false));
+        assert_eq!(tracker.held(), [Modifier::Ctrl]);
+        assert!(tracker.key("kbd", RIGHT_CTRL, false));
+        assert_eq!(tracker.held(), []);
+    }
+
+    #[test]
+    fn held_on_two_devices() {
+        let mut tracker = ModifierTracker::default();
+        assert!(tracker.key("internal", LEFT_CTRL, true));
+        assert!(!tracker.key("usb", LEFT_CTRL, true));
+        assert!(tracker.key("usb", LEFT_SHIFT, true));
+        assert_eq!(tracker.held(), [Modifier::Ctrl, Modifier::Shift]);
+        assert!(!tracker.key("internal", LEFT_CTRL, false));
+        assert_eq!(tracker.held(), [Modifier::Ctrl, Modifier::Shift]);
+        // A repeat of a key already down changes nothing
+        assert!(!tracker.key("usb", LEFT_SHIFT, true));
+        assert!(tracker.key("usb", LEFT_CTRL, false));
+        assert_eq!(tracker.held(), [Modifier::Shift]);
+    }
+
+    #[test]
+    fn removing_a_device_releases_only_its_keys() {
+        let mut tracker = ModifierTracker::default();
+        tracker.key("internal", LEFT_CTRL, true);
+        tracker.key("usb", LEFT_CTRL, true);
+        tracker.key("usb", LEFT_SHIFT, true);
+        assert!(tracker.remove_device("usb"));
+        assert_eq!(tracker.held(), [Modifier::Ctrl]);
+        assert!(!tracker.remove_device("usb"));
+        tracker.key("usb", LEFT_CTRL, true);
+        assert!(!tracker.remove_device("usb"));
+        assert!(tracker.remove_device("internal"));
+        assert_eq!(tracker.held(), []);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+// How far a finger has to move sideways before a touch becomes a pan
+pub const PAN_THRESHOLD_PX: f64 = 24.0;
+// Touches on scrollable layers wait this long to see if they turn into a pan
+// before pressing the button
+pub const PRESS_DELAY: Duration = Duration::from_millis(120);
+// Inertia loses this fraction of its speed every second
+const FRICTION: f64 = 0.95;
+// Slower than this in pixels per second and the content stops
+const MIN_VELOCITY: f64 = 20.0;
+// A finger resting this long before lifting off doesn't fling
+const FLING_TIMEOUT: Duration = Duration::from_millis(60);
+
+// Horizontal scroll position of a layer, with inertia after a fling
+#[derive(Default)]
+pub struct Scroll {
+    pub offset: f64,
+    velocity: f64,
+    last_step: Option<Instant>,
+}
+
+impl Scroll {
+    pub fn scroll_by(&mut self, dx: f64, max_offset: f64) {
+        self.velocity = 0.0;
+        self.offset = (self.offset + dx).clamp(0.0, max_offset);
+    }
+    pub fn fling(&mut self, velocity: f64) {
+        if velocity.abs() >= MIN_VELOCITY {
+            self.velocity = velocity;
+            self.last_step = Some(Instant::now());
+        }
+    }
+    pub fn running(&self) -> bool {
+        self.velocity != 0.0
+    }
+    // Advance the inertia to now and keep the offset within bounds
+    pub fn step(&mut self, max_offset: f64) {
+        if self.running() {
+            let now = Instant::now();
+            let dt = self.last_step.map_or(0.0, |t| (now - t).as_secs_f64());
+            self.last_step = Some(now);
+            self.offset += self.velocity * dt;
+            self.velocity *= (1.0 - FRICTION).powf(dt);
+            if self.velocity.abs() < MIN_VELOCITY || self.offset <= 0.0 || self.offset >= max_offset
+            {
+                self.velocity = 0.0;
+            }
+        }
+        self.offset = self.offset.clamp(0.0, max_offset);
+    }
+}
+
+// Fingers dragging the content of a layer, in logical coordinates
+pub struct Pan {
+    pub layer: usize,
+    last_x: HashMap<u32, f64>,
+    last_move: Instant,
+    velocity: f64,
+}
+
+impl Pan {
+    pub fn new(layer: usize) -> Pan {
+        Pan {
+            layer,
+            last_x: HashMap::new(),
+            last_move: Instant::now(),
+            velocity: 0.0,
+        }
+    }
+    pub fn add_finger(&mut self, slot: u32, x: f64) {
+        self.last_x.insert(slot, x);
+    }
+    pub fn has_finger(&self, slot: u32) -> bool {
+        self.last_x.contains_key(&slot)
+    }
+    // Returns whether it was the last finger
+    pub fn remove_finger(&mut self, slot: u32) -> bool {
+        self.last_x.remove(&slot);
+        self.last_x.is_empty()
+    }
+    // How far the content moves for a finger moving to x, averaged over all fingers
+    pub fn move_finger(&mut self, slot: u32, x: f64) -> f64 {
+        let Some(last_x) = self.last_x.insert(slot, x) else {
+            return 0.0;
+        };
+        let dx = (last_x - x) / self.last_x.len() as f64;
+        let now = Instant::now();
+        let dt = (now - self.last_move).as_secs_f64();
+        if dt > 0.0 {
+            // Smoothed, single motion events are too noisy
+            self.velocity = self.velocity * 0.2 + dx / dt * 0.8;
+        }
+        self.last_move = now;
+        dx
+    }
+    // Speed in pixels per second to keep scrolling at once the fingers lift
+    pub fn fling_velocity(&self) -> f64 {
+        if self.last_move.elapsed() > FLING_TIMEOUT {
+            0.0
+        } else {
+            self.velocity
+        }
+    }
+}
+
+// A touch on a scrollable layer that hasn't pressed its button yet
+pub struct PendingTouch {
+    pub layer: usize,
+    pub btn: usize,
+    pub start_x: f64,
+    pub x: f64,
+    pub down_at: Instant,
+    // Waits for the finger to lift or a long press instead of pressing after a delay
+    pub hold: bool,
+}
+
+impl PendingTouch {
+    pub fn press_time(&self) -> Option<Instant> {
+        (!self.hold).then_some(self.down_at + PRESS_DELAY)
+    }
+}
@@ -0,0 +1,123 @@
+use crate::crash::take_panic_summary;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// A run that lasted at least this long resets the backoff
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+pub struct Supervisor {
+    backoff: Duration,
+    restarts: u32,
+}
+
+impl Supervisor {
+    pub fn new() -> Supervisor {
+        Supervisor {
+            backoff: INITIAL_BACKOFF,
+            restarts: 0,
+        }
+    }
+    // Delay before the next restart, doubling with every failure in a row
+    fn next_backoff(&mut self, run_time: Duration) -> Duration {
+        if run_time >= HEALTHY_RUN {
+            self.backoff = INITIAL_BACKOFF;
+        }
+        let delay = self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        self.restarts += 1;
+        delay
+    }
+    // Keep calling run until it returns. After each panic, recover gets the error
+    // summary and the state is given a chance to clean up before the next attempt.
+    pub fn run<S>(
+        &mut self,
+        state: &mut S,
+        mut run: impl FnMut(&mut S),
+        mut recover: impl FnMut(&mut S, &str),
+        sleep: impl Fn(Duration),
+    ) {
+        loop {
+            let started = Instant::now();
+            if panic::catch_unwind(AssertUnwindSafe(|| run(state))).is_ok() {
+                return;
+            }
+            let summary = take_panic_summary().unwrap_or_else(|| "unknown error".to_string());
+            let delay = self.next_backoff(started.elapsed());
+            println!(
+                "Main loop failed: {summary}, restart #{} in {:.1}s",
+                self.restarts,
+                delay.as_secs_f64()
+            );
+            recover(state, &summary);
+            sleep(delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crash::install_panic_hook;
+    use std::cell::RefCell;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut supervisor = Supervisor::new();
+        let delays: Vec<u64> = (0..10)
+            .map(|_| supervisor.next_backoff(Duration::ZERO).as_millis() as u64)
+            .collect();
+        assert_eq!(
+            delays,
+            [
+                500, 1000, 2000, 4000, 8000, 16000, 32000, 60000, 60000, 60000
+            ]
+        );
+        assert_eq!(supervisor.restarts, 10);
+    }
+
+    #[test]
+    fn healthy_run_resets_backoff() {
+        let mut supervisor = Supervisor::new();
+        for _ in 0..4 {
+            supervisor.next_backoff(Duration::from_secs(1));
+        }
+        assert_eq!(supervisor.next_backoff(HEALTHY_RUN), INITIAL_BACKOFF);
+        assert_eq!(supervisor.next_backoff(Duration::ZERO), INITIAL_BACKOFF * 2);
+    }
+
+    #[test]
+    fn restarts_after_injected_failures() {
+        install_panic_hook();
+        let slept = RefCell::new(Vec::new());
+        let mut recovered = Vec::new();
+        let mut runs = 0;
+        Supervisor::new().run(
+            &mut runs,
+            |runs| {
+                *runs += 1;
+                if *runs <= 3 {
+                    panic!("injected failure {runs}");
+                }
+            },
+            |runs, summary| recovered.push((*runs, summary.to_string())),
+            |delay| slept.borrow_mut().push(delay),
+        );
+        assert_eq!(runs, 4);
+        assert_eq!(
+            recovered,
+            [
+                (1, "injected failure 1".to_string()),
+                (2, "injected failure 2".to_string()),
+                (3, "injected failure 3".to_string()),
+            ]
+        );
+        assert_eq!(
+            slept.into_inner(),
+            [500, 1000, 2000].map(Duration::from_millis)
+        );
+    }
+}
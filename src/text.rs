@@ -0,0 +1,168 @@
+use crate::fonts::{FontConfig, FontConfigError, Pattern};
+use cairo::Context;
+use pangocairo::{
+    functions::{create_layout, show_layout},
+    pango::{self, Alignment, EllipsizeMode, FontDescription, Layout, Style, Weight, WrapMode},
+};
+use std::{collections::HashMap, rc::Rc};
+
+// Pick the weight pango names closest to an OpenType one
+fn pango_weight(weight: i32) -> Weight {
+    match weight {
+        ..150 => Weight::Thin,
+        150..250 => Weight::Ultralight,
+        250..325 => Weight::Light,
+        325..365 => Weight::Semilight,
+        365..390 => Weight::Book,
+        390..450 => Weight::Normal,
+        450..550 => Weight::Medium,
+        550..650 => Weight::Semibold,
+        650..750 => Weight::Bold,
+        750..850 => Weight::Ultrabold,
+        850..950 => Weight::Heavy,
+        _ => Weight::Ultraheavy,
+    }
+}
+
+// Resolve a fontconfig pattern into the weight and style of its best match, and the
+// families pango should try in turn for characters the first one lacks. Also returns
+// whether the match is the family asked for, as fontconfig always finds some font.
+fn resolve_font(
+    fontconfig: &FontConfig,
+    name: &str,
+) -> Result<(FontDescription, bool), FontConfigError> {
+    let mut pattern = Pattern::new(name)?;
+    let requested = pattern.get_families().into_iter().next();
+    fontconfig.perform_substitutions(&mut pattern);
+    let pat_match = fontconfig.match_pattern(&pattern)?;
+    let matched = pat_match.get_family().to_string();
+    // Aliases like sans-serif are replaced by the families they stand for. A name
+    // still ahead of the match was passed over because no font has it.
+    let found = requested.is_none_or(|requested| {
+        let substituted = pattern.get_families();
+        let position = |family: &str| {
+            substituted
+                .iter()
+                .position(|f| f.eq_ignore_ascii_case(family))
+        };
+        requested.eq_ignore_ascii_case(&matched)
+            || position(&requested).is_none_or(|r| position(&matched).is_some_and(|m| m < r))
+    });
+    let mut families = vec![matched];
+    for family in fontconfig.sort_families(&pattern) {
+        if !families.contains(&family) {
+            families.push(family);
+        }
+    }
+    let mut font = FontDescription::new();
+    font.set_family(&families.join(","));
+    font.set_weight(pango_weight(pat_match.get_weight()));
+    // FC_SLANT_ITALIC and FC_SLANT_OBLIQUE
+    font.set_style(match pat_match.get_slant() {
+        100 => Style::Italic,
+        110 => Style::Oblique,
+        _ => Style::Normal,
+    });
+    Ok((font, found))
+}
+
+// Buttons asking for the same font and size share a single description
+pub struct FontCache {
+    fontconfig: FontConfig,
+    default_pattern: String,
+    default_size: f64,
+    fonts: HashMap<(String, i32), Rc<FontDescription>>,
+}
+
+impl FontCache {
+    pub fn new(default_pattern: &str, default_size: f64) -> FontCache {
+        FontCache {
+            fontconfig: FontConfig::new(),
+            default_pattern: default_pattern.to_string(),
+            default_size,
+            fonts: HashMap::new(),
+        }
+    }
+    // Font size is in pixels, missing values are taken from the defaults
+    pub fn get(&mut self, pattern: Option<&str>, size: Option<f64>) -> Rc<FontDescription> {
+        let pattern = pattern.unwrap_or(&self.default_pattern).to_string();
+        let size = (size.unwrap_or(self.default_size) * pango::SCALE as f64) as i32;
+        let key = (pattern, size);
+        if let Some(font) = self.fonts.get(&key) {
+            return font.clone();
+        }
+        let font = match resolve_font(&self.fontconfig, &key.0) {
+            Ok((mut font, true)) => {
+                font.set_absolute_size(size as f64);
+                Rc::new(font)
+            }
+            Ok(_) | Err(_) if key.0 != self.default_pattern => {
+                println!("Unable to find font \"{}\", using the default one", key.0);
+                self.get(None, Some(size as f64 / pango::SCALE as f64))
+            }
+            // Nothing left to fall back to, so take whatever fontconfig found
+            Ok((mut font, false)) => {
+                println!(
+                    "Unable to find font \"{}\", using {}",
+                    key.0,
+                    font.family().unwrap_or_default().split(',').next().unwrap()
+                );
+                font.set_absolute_size(size as f64);
+                Rc::new(font)
+            }
+            Err(_) => panic!(
+                "Unable to find specified font. If you are using the default config, make sure you have at least one font installed"
+            ),
+        };
+        self.fonts.insert(key, font.clone());
+        font
+    }
+}
+
+// How a button lays out its label
+#[derive(Clone, Debug)]
+pub struct TextStyle {
+    pub font: Rc<FontDescription>,
+    // Let labels that don't fit on one line continue on a second one
+    pub wrap: bool,
+}
+
+impl TextStyle {
+    // Lay out text no wider than max_width, cut short with an ellipsis if it doesn't fit
+    pub fn layout(&self, c: &Context, text: &str, max_width: f64, alignment: Alignment) -> Layout {
+        let layout = create_layout(c);
+        layout.set_font_description(Some(&self.font));
+        layout.set_text(text);
+        layout.set_width((max_width * pango::SCALE as f64) as i32);
+        layout.set_alignment(alignment);
+        layout.set_ellipsize(EllipsizeMode::End);
+        if self.wrap {
+            layout.set_wrap(WrapMode::WordChar);
+            // Negative heights are a line count
+            layout.set_height(-2);
+        }
+        layout
+    }
+    // Draw a layout with its left edge at x, inked part centered vertically on the panel
+    pub fn show_at(&self, c: &Context, layout: &Layout, x: f64, height: i32, y_shift: f64) {
+        let (ink, _) = layout.pixel_extents();
+        c.move_to(
+            x,
+            y_shift + (height as f64 / 2.0 - ink.y() as f64 - ink.height() as f64 / 2.0).round(),
+        );
+        show_layout(c, layout);
+    }
+    // Draw text centered on the button, so single line labels sit where show_text put them
+    pub fn show(
+        &self,
+        c: &Context,
+        text: &str,
+        button_left_edge: f64,
+        button_width: u64,
+        height: i32,
+        y_shift: f64,
+    ) {
+        let layout = self.layout(c, text, button_width as f64, Alignment::Center);
+        self.show_at(c, &layout, button_left_edge, height, y_shift);
+    }
+}
@@ -0,0 +1,123 @@
+use cairo::Context;
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Color {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    pub a: f64,
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    // Accepts "#rrggbb" and "#rrggbbaa"
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value.strip_prefix('#').unwrap_or(&value);
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return Err(format!(
+                "Invalid color {value}, expected #rrggbb or #rrggbbaa"
+            ));
+        }
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .map(|c| u8::from_str_radix(c, 16).map(|c| c as f64 / 255.0))
+                .unwrap_or(Ok(1.0))
+                .map_err(|_| format!("Invalid color {value}"))
+        };
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+            a: channel(6)?,
+        })
+    }
+}
+
+impl Color {
+    pub const fn rgb(r: f64, g: f64, b: f64) -> Color {
+        Color { r, g, b, a: 1.0 }
+    }
+    pub fn set_source(&self, c: &Context) {
+        c.set_source_rgba(self.r, self.g, self.b, self.a);
+    }
+    // Linear blend towards another color, t = 0 gives self and t = 1 gives other
+    pub fn mix(&self, other: &Color, t: f64) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        Color {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t,
+        }
+    }
+}
+
+// Used both for the global [Theme] section and per button Style overrides
+#[derive(Deserialize, Default, Clone, Copy, Debug)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub background: Option<Color>,
+    pub button_fill: Option<Color>,
+    pub pressed_fill: Option<Color>,
+    pub outline: Option<Color>,
+    pub outline_width: Option<f64>,
+    pub text: Option<Color>,
+    pub pressed_text: Option<Color>,
+    pub icon_color: Option<Color>,
+    pub warning: Option<Color>,
+    pub positive: Option<Color>,
+    pub corner_radius: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    pub background: Color,
+    pub button_fill: Color,
+    pub pressed_fill: Color,
+    pub outline: Color,
+    pub outline_width: f64,
+    pub text: Color,
+    // Text and recolored icons on pressed buttons, same as text when unset
+    pub pressed_text: Option<Color>,
+    // Recolored icons, which follow the text color when unset
+    pub icon_color: Option<Color>,
+    pub warning: Color,
+    pub positive: Color,
+    pub corner_radius: f64,
+}
+
+impl Theme {
+    pub fn new(corner_radius: f64) -> Theme {
+        Theme {
+            background: Color::rgb(0.0, 0.0, 0.0),
+            button_fill: Color::rgb(0.2, 0.2, 0.2),
+            pressed_fill: Color::rgb(0.4, 0.4, 0.4),
+            outline: Color::rgb(1.0, 1.0, 1.0),
+            outline_width: 0.0,
+            text: Color::rgb(1.0, 1.0, 1.0),
+            pressed_text: None,
+            icon_color: None,
+            warning: Color::rgb(1.0, 0.0, 0.0),
+            positive: Color::rgb(0.0, 1.0, 0.0),
+            corner_radius,
+        }
+    }
+    pub fn with_overrides(&self, cfg: &ThemeConfig) -> Theme {
+        Theme {
+            background: cfg.background.unwrap_or(self.background),
+            button_fill: cfg.button_fill.unwrap_or(self.button_fill),
+            pressed_fill: cfg.pressed_fill.unwrap_or(self.pressed_fill),
+            outline: cfg.outline.unwrap_or(self.outline),
+            outline_width: cfg.outline_width.unwrap_or(self.outline_width),
+            text: cfg.text.unwrap_or(self.text),
+            pressed_text: cfg.pressed_text.or(self.pressed_text),
+            icon_color: cfg.icon_color.or(self.icon_color),
+            warning: cfg.warning.unwrap_or(self.warning),
+            positive: cfg.positive.unwrap_or(self.positive),
+            corner_radius: cfg.corner_radius.unwrap_or(self.corner_radius),
+        }
+    }
+}
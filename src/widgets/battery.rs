@@ -1,20 +1,20 @@
 use cairo::Context;
-use input_linux::Key;
 use starship_battery::Manager;
 use std::time::{Duration, Instant};
 
 use super::TWidget;
+use crate::{action::Action, text::TextStyle, theme::Theme};
 
 pub struct BatteryWidget {
     pub changed: bool,
     pub active: bool,
-    pub action: Key,
+    pub action: Action,
     pub last_draw_time: Instant,
     pub manager: Manager,
 }
 
 impl BatteryWidget {
-    pub fn new(_text: String, action: Key) -> Self {
+    pub fn new(_text: String, action: Action) -> Self {
         let manager = starship_battery::Manager::new().expect("Cant bind battery");
         // Load in the battery status icons (TODO)
         Self {
@@ -31,6 +31,8 @@ impl TWidget for BatteryWidget {
     fn render(
         &mut self,
         c: &Context,
+        theme: &Theme,
+        text_style: &TextStyle,
         height: i32,
         button_left_edge: f64,
         button_width: u64,
@@ -40,14 +42,14 @@ impl TWidget for BatteryWidget {
             && let Some(battery) = batteries.filter_map(|b| b.ok()).next() {
                 let soc = battery.state_of_charge();
                 if soc.value < 0.2 {
-                    c.set_source_rgb(1.0, 0.0, 0.0);
+                    theme.warning.set_source(c);
                 } else if soc.value < 0.5 {
-                    c.set_source_rgb(1.0, 0.5, 0.5);
+                    theme.text.mix(&theme.warning, 0.5).set_source(c);
                 }
 
                 match battery.state() {
                     starship_battery::State::Unknown => {}
-                    starship_battery::State::Charging => c.set_source_rgb(0.0, 1.0, 0.0),
+                    starship_battery::State::Charging => theme.positive.set_source(c),
                     starship_battery::State::Discharging => {}
                     starship_battery::State::Empty => {}
                     starship_battery::State::Full => {}
@@ -60,13 +62,7 @@ impl TWidget for BatteryWidget {
 
                 let text = format!("{:2.0}%{}", soc.value * 100.0, dir_sym);
 
-                let text_extent = c.text_extents(&text).unwrap();
-                c.move_to(
-                    button_left_edge
-                        + (button_width as f64 / 2.0 - text_extent.width() / 2.0).round(),
-                    y_shift + (height as f64 / 2.0 + text_extent.height() / 2.0).round(),
-                );
-                c.show_text(&text).unwrap();
+                text_style.show(c, &text, button_left_edge, button_width, height, y_shift);
                 self.last_draw_time = Instant::now();
             }
     }
@@ -80,8 +76,8 @@ impl TWidget for BatteryWidget {
         }
     }
 
-    fn get_action(&self) -> Key {
-        self.action
+    fn get_action(&self) -> &Action {
+        &self.action
     }
     fn next_draw_time(&self) -> Option<Instant> {
         Some(self.last_draw_time + Duration::from_secs(5))
@@ -1,14 +1,56 @@
 use super::{
-    TWidget, TextButton, TimeWidget, battery::BatteryWidget, image_button::ImageButton,
-    memory::MemoryWidget, processor::ProcessorWidget,
+    TWidget, TextButton, TimeWidget, battery::BatteryWidget, graph::GraphWidget,
+    icon_text_button::IconTextButton, image_button::ImageButton, memory::MemoryWidget,
+    processor::ProcessorWidget, slider::SliderWidget,
 };
-use crate::config::ButtonConfig;
+use crate::{config::ButtonConfig, layout::LayoutMetrics};
+use std::time::Duration;
 
-pub fn new_widget_from_config(cfg: ButtonConfig) -> Box<dyn TWidget> {
-    if let Some(text) = cfg.text {
+const DEFAULT_GRAPH_HISTORY: usize = 30;
+const DEFAULT_SAMPLE_INTERVAL_MS: u64 = 2000;
+
+pub fn new_widget_from_config(cfg: ButtonConfig, layout: &LayoutMetrics) -> Box<dyn TWidget> {
+    // Symbolic icons are meant to be drawn in the text color
+    let recolor = cfg
+        .recolor
+        .unwrap_or(cfg.icon.as_ref().is_some_and(|i| i.ends_with("-symbolic")));
+    if let (Some(icon), Some(text)) = (&cfg.icon, &cfg.text) {
+        Box::new(IconTextButton::new(
+            icon,
+            cfg.theme,
+            layout.icon_size,
+            recolor,
+            text,
+            cfg.icon_position.unwrap_or_default(),
+            cfg.align.unwrap_or_default(),
+            cfg.icon_spacing.unwrap_or(layout.button_spacing / 2.0),
+            cfg.action,
+        ))
+    } else if let Some(text) = cfg.text {
         Box::new(TextButton::new(&text, cfg.action))
     } else if let Some(icon) = cfg.icon {
-        Box::new(ImageButton::new(&icon, cfg.theme, cfg.action))
+        Box::new(ImageButton::new(
+            &icon,
+            cfg.theme,
+            layout.icon_size,
+            recolor,
+            cfg.action,
+        ))
+    } else if let Some(source) = cfg.graph {
+        Box::new(GraphWidget::new(
+            source,
+            cfg.graph_style.unwrap_or_default(),
+            cfg.history.unwrap_or(DEFAULT_GRAPH_HISTORY),
+            Duration::from_millis(
+                cfg.sample_interval
+                    .unwrap_or(DEFAULT_SAMPLE_INTERVAL_MS)
+                    .max(100),
+            ),
+            layout,
+            cfg.action,
+        ))
+    } else if let Some(source) = &cfg.slider {
+        Box::new(SliderWidget::new(source, layout, cfg.action))
     } else if let Some(text) = cfg.processor {
         Box::new(ProcessorWidget::new(text, cfg.action))
     } else if let Some(text) = cfg.memory {
@@ -0,0 +1,190 @@
+use cairo::Context;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+use super::TWidget;
+use crate::{
+    action::Action,
+    layout::LayoutMetrics,
+    metrics::{CPUUsage, History, MemoryUsage},
+    text::TextStyle,
+    theme::{Color, Theme},
+};
+
+// Metric plotted by a graph button
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum GraphSource {
+    Cpu,
+    Memory,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub enum GraphStyle {
+    #[default]
+    Line,
+    Area,
+    Bars,
+}
+
+pub struct GraphWidget {
+    pub source: GraphSource,
+    pub style: GraphStyle,
+    pub history: History,
+    pub interval: Duration,
+    // Distance kept between the plot and the button edges
+    pub inset_x: f64,
+    pub inset_y: f64,
+    pub cpu: CPUUsage,
+    pub changed: bool,
+    pub active: bool,
+    pub action: Action,
+    pub last_sample_time: Option<Instant>,
+}
+
+impl GraphWidget {
+    pub fn new(
+        source: GraphSource,
+        style: GraphStyle,
+        history: usize,
+        interval: Duration,
+        layout: &LayoutMetrics,
+        action: Action,
+    ) -> Self {
+        // CPU load is measured between two samples, so take the first one right away
+        let mut cpu = CPUUsage::default();
+        cpu.sample();
+        Self {
+            source,
+            style,
+            history: History::new(history),
+            interval,
+            inset_x: layout.corner_radius.max(layout.button_spacing / 2.0),
+            inset_y: layout.vertical_padding + layout.corner_radius / 2.0,
+            cpu,
+            action,
+            active: false,
+            changed: false,
+            last_sample_time: None,
+        }
+    }
+    // Current value of the metric from 0.0 to 1.0, if there is one
+    fn sample(&mut self) -> Option<f64> {
+        let percent = match self.source {
+            GraphSource::Cpu => {
+                let readings = self.cpu.sample();
+                if readings.idle == 0 && readings.user == 0 {
+                    return None;
+                }
+                100 - readings.idle.min(100)
+            }
+            GraphSource::Memory => MemoryUsage::sample().used,
+        };
+        Some((percent as f64 / 100.0).clamp(0.0, 1.0))
+    }
+    fn sample_due(&self) -> bool {
+        self.last_sample_time
+            .is_none_or(|t| t.elapsed() >= self.interval)
+    }
+}
+
+impl TWidget for GraphWidget {
+    fn render(
+        &mut self,
+        c: &Context,
+        theme: &Theme,
+        _text_style: &TextStyle,
+        height: i32,
+        button_left_edge: f64,
+        button_width: u64,
+        y_shift: f64,
+    ) {
+        if self.sample_due() {
+            if let Some(value) = self.sample() {
+                self.history.push(value);
+            }
+            self.last_sample_time = Some(Instant::now());
+        }
+
+        // Turn the graph towards the warning color as the metric gets high
+        let latest = self.history.latest().unwrap_or(0.0);
+        let color = theme.text.mix(&theme.warning, latest.powi(2));
+        color.set_source(c);
+
+        let left = button_left_edge + self.inset_x;
+        let width = button_width as f64 - self.inset_x * 2.0;
+        let bottom = height as f64 - self.inset_y + y_shift;
+        let plot_height = height as f64 - self.inset_y * 2.0;
+        if width <= 0.0 || plot_height <= 0.0 {
+            return;
+        }
+        // Newest sample on the right edge, older ones scroll off to the left
+        let capacity = self.history.capacity();
+        let skipped = capacity - self.history.len();
+        let y = |v: f64| bottom - v * plot_height;
+
+        match self.style {
+            GraphStyle::Bars => {
+                let slot = width / capacity as f64;
+                let gap = (slot / 4.0).min(2.0);
+                for (i, v) in self.history.iter().enumerate() {
+                    let x = left + (skipped + i) as f64 * slot;
+                    c.rectangle(x + gap / 2.0, y(v), slot - gap, v * plot_height);
+                }
+                c.fill().unwrap();
+            }
+            GraphStyle::Line | GraphStyle::Area => {
+                let step = width / (capacity - 1) as f64;
+                let x = |i: usize| left + (skipped + i) as f64 * step;
+                for (i, v) in self.history.iter().enumerate() {
+                    c.line_to(x(i), y(v));
+                }
+                if self.history.len() < 2 {
+                    c.new_path();
+                    return;
+                }
+                c.set_line_width((plot_height / 16.0).max(1.0));
+                c.set_line_join(cairo::LineJoin::Round);
+                if let GraphStyle::Line = self.style {
+                    c.stroke().unwrap();
+                    return;
+                }
+                c.stroke_preserve().unwrap();
+                c.line_to(x(self.history.len() - 1), bottom);
+                c.line_to(x(0), bottom);
+                c.close_path();
+                Color {
+                    a: color.a * 0.4,
+                    ..color
+                }
+                .set_source(c);
+                c.fill().unwrap();
+            }
+        }
+    }
+    fn set_active(&mut self, active: bool) -> bool {
+        if self.active != active {
+            self.active = active;
+            self.changed = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn get_action(&self) -> &Action {
+        &self.action
+    }
+    fn next_draw_time(&self) -> Option<Instant> {
+        Some(self.last_sample_time.unwrap_or_else(Instant::now) + self.interval)
+    }
+    fn changed(&self) -> bool {
+        self.changed || self.sample_due()
+    }
+    fn active(&self) -> bool {
+        self.active
+    }
+
+    fn reset_changed(&mut self) {
+        self.changed = false;
+    }
+}
@@ -0,0 +1,171 @@
+use std::time::Instant;
+
+use super::TWidget;
+use crate::{action::Action, text::TextStyle, theme::Theme};
+use crate::{button_image::ButtonImage, graphics_load::try_load_image};
+use anyhow::Result;
+use cairo::Context;
+use pangocairo::pango;
+use serde::Deserialize;
+
+// Where the icon goes relative to the label
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum IconPosition {
+    #[default]
+    Left,
+    Right,
+    Above,
+}
+
+// How the icon and label are placed within the button as a group
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Align {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+pub struct IconTextButton {
+    pub image: ButtonImage,
+    pub icon_size: i32,
+    // Tint the icon with the icon or text color instead of drawing it as is
+    pub recolor: bool,
+    pub text: String,
+    pub position: IconPosition,
+    pub align: Align,
+    // Gap between the icon and the label, also kept from the button edges
+    pub spacing: f64,
+    pub changed: bool,
+    pub active: bool,
+    pub action: Action,
+}
+
+impl IconTextButton {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        path: impl AsRef<str>,
+        theme: Option<impl AsRef<str>>,
+        icon_size: i32,
+        recolor: bool,
+        text: &str,
+        position: IconPosition,
+        align: Align,
+        spacing: f64,
+        action: Action,
+    ) -> Self {
+        let image = try_load_image(path, theme, icon_size).expect("failed to load icon");
+        Self {
+            action,
+            active: false,
+            changed: false,
+            image,
+            icon_size,
+            recolor,
+            text: text.to_owned(),
+            position,
+            align,
+            spacing,
+        }
+    }
+    // Left edge of a group of the given width inside the button
+    fn group_left(&self, button_left_edge: f64, button_width: f64, group_width: f64) -> f64 {
+        let x = match self.align {
+            Align::Left => self.spacing,
+            Align::Center => (button_width - group_width) / 2.0,
+            Align::Right => button_width - self.spacing - group_width,
+        };
+        button_left_edge + x.max(0.0).round()
+    }
+}
+
+impl TWidget for IconTextButton {
+    fn render(
+        &mut self,
+        c: &Context,
+        theme: &Theme,
+        text_style: &TextStyle,
+        height: i32,
+        button_left_edge: f64,
+        button_width: u64,
+        y_shift: f64,
+    ) {
+        let button_width = button_width as f64;
+        let icon_size = self.icon_size as f64;
+        if self.position == IconPosition::Above {
+            let max_width = button_width - self.spacing * 2.0;
+            let layout = text_style.layout(c, &self.text, max_width, pango::Alignment::Left);
+            let (ink, logical) = layout.pixel_extents();
+            // The icon gives up whatever room the label needs below it
+            let icon_size = icon_size.min(height as f64 - ink.height() as f64 - self.spacing);
+            let top = y_shift
+                + ((height as f64 - icon_size - self.spacing - ink.height() as f64) / 2.0).round();
+            let icon_x = self.group_left(button_left_edge, button_width, icon_size);
+            self.image
+                .draw(c, icon_x, top, icon_size, self.recolor, theme.icon_color);
+            let text_x = self.group_left(button_left_edge, button_width, logical.width() as f64);
+            c.move_to(text_x, top + icon_size + self.spacing - ink.y() as f64);
+            pangocairo::functions::show_layout(c, &layout);
+            return;
+        }
+
+        let max_width = button_width - icon_size - self.spacing * 3.0;
+        let layout = text_style.layout(c, &self.text, max_width, pango::Alignment::Left);
+        let (_, logical) = layout.pixel_extents();
+        let text_width = logical.width() as f64;
+        let left = self.group_left(
+            button_left_edge,
+            button_width,
+            icon_size + self.spacing + text_width,
+        );
+        let (icon_x, text_x) = match self.position {
+            IconPosition::Right => (left + text_width + self.spacing, left),
+            _ => (left, left + icon_size + self.spacing),
+        };
+        let icon_y = y_shift + ((height as f64 - icon_size) / 2.0).round();
+        self.image
+            .draw(c, icon_x, icon_y, icon_size, self.recolor, theme.icon_color);
+        text_style.show_at(c, &layout, text_x, height, y_shift);
+    }
+    fn set_active(&mut self, active: bool) -> bool {
+        if self.active != active {
+            self.active = active;
+            self.changed = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn get_action(&self) -> &Action {
+        &self.action
+    }
+
+    fn next_draw_time(&self) -> Option<Instant> {
+        None
+    }
+
+    fn text(&self) -> Option<&str> {
+        Some(&self.text)
+    }
+    fn set_text(&mut self, text: &str) -> bool {
+        self.text = text.to_owned();
+        self.changed = true;
+        true
+    }
+    fn set_icon(&mut self, icon: &str, theme: Option<&str>) -> Result<bool> {
+        self.image = try_load_image(icon, theme, self.icon_size)?;
+        self.changed = true;
+        Ok(true)
+    }
+    fn changed(&self) -> bool {
+        self.changed
+    }
+    fn active(&self) -> bool {
+        self.active
+    }
+
+    fn reset_changed(&mut self) {
+        self.changed = false;
+    }
+}
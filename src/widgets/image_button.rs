@@ -1,26 +1,37 @@
 use std::time::Instant;
 
 use super::TWidget;
-use crate::{button_image::ButtonImage, constants::ICON_SIZE, graphics_load::try_load_image};
+use crate::{action::Action, text::TextStyle, theme::Theme};
+use crate::{button_image::ButtonImage, graphics_load::try_load_image};
+use anyhow::Result;
 use cairo::Context;
-use input_linux::Key;
-use librsvg_rebind::{Rectangle, prelude::HandleExt};
 
 pub struct ImageButton {
     pub image: ButtonImage,
+    pub icon_size: i32,
+    // Tint the icon with the icon or text color instead of drawing it as is
+    pub recolor: bool,
     pub changed: bool,
     pub active: bool,
-    pub action: Key,
+    pub action: Action,
 }
 
 impl ImageButton {
-    pub fn new(path: impl AsRef<str>, theme: Option<impl AsRef<str>>, action: Key) -> Self {
-        let image = try_load_image(path, theme).expect("failed to load icon");
+    pub fn new(
+        path: impl AsRef<str>,
+        theme: Option<impl AsRef<str>>,
+        icon_size: i32,
+        recolor: bool,
+        action: Action,
+    ) -> Self {
+        let image = try_load_image(path, theme, icon_size).expect("failed to load icon");
         Self {
             action,
             active: false,
             changed: false,
             image,
+            icon_size,
+            recolor,
         }
     }
 }
@@ -29,29 +40,18 @@ impl TWidget for ImageButton {
     fn render(
         &mut self,
         c: &Context,
+        theme: &Theme,
+        _text_style: &TextStyle,
         height: i32,
         button_left_edge: f64,
         button_width: u64,
         y_shift: f64,
     ) {
-        match &self.image {
-            ButtonImage::Svg(svg) => {
-                let x =
-                    button_left_edge + (button_width as f64 / 2.0 - (ICON_SIZE / 2) as f64).round();
-                let y = y_shift + ((height as f64 - ICON_SIZE as f64) / 2.0).round();
-
-                svg.render_document(c, &Rectangle::new(x, y, ICON_SIZE as f64, ICON_SIZE as f64))
-                    .unwrap();
-            }
-            ButtonImage::Bitmap(surf) => {
-                let x =
-                    button_left_edge + (button_width as f64 / 2.0 - (ICON_SIZE / 2) as f64).round();
-                let y = y_shift + ((height as f64 - ICON_SIZE as f64) / 2.0).round();
-                c.set_source_surface(surf, x, y).unwrap();
-                c.rectangle(x, y, ICON_SIZE as f64, ICON_SIZE as f64);
-                c.fill().unwrap();
-            }
-        }
+        let icon_size = self.icon_size as f64;
+        let x = button_left_edge + (button_width as f64 / 2.0 - icon_size / 2.0).round();
+        let y = y_shift + ((height as f64 - icon_size) / 2.0).round();
+        self.image
+            .draw(c, x, y, icon_size, self.recolor, theme.icon_color);
     }
     fn set_active(&mut self, active: bool) -> bool {
         if self.active != active {
@@ -63,14 +63,19 @@ impl TWidget for ImageButton {
         }
     }
 
-    fn get_action(&self) -> Key {
-        self.action
+    fn get_action(&self) -> &Action {
+        &self.action
     }
 
     fn next_draw_time(&self) -> Option<Instant> {
         None
     }
 
+    fn set_icon(&mut self, icon: &str, theme: Option<&str>) -> Result<bool> {
+        self.image = try_load_image(icon, theme, self.icon_size)?;
+        self.changed = true;
+        Ok(true)
+    }
     fn changed(&self) -> bool {
         self.changed
     }
@@ -1,20 +1,19 @@
 use cairo::Context;
-use input_linux::Key;
 use std::time::{Duration, Instant};
 
-use crate::metrics::MemoryUsage;
+use crate::{action::Action, metrics::MemoryUsage, text::TextStyle, theme::Theme};
 
 use super::TWidget;
 
 pub struct MemoryWidget {
     pub changed: bool,
     pub active: bool,
-    pub action: Key,
+    pub action: Action,
     pub last_draw_time: Instant,
 }
 
 impl MemoryWidget {
-    pub fn new(_text: String, action: Key) -> Self {
+    pub fn new(_text: String, action: Action) -> Self {
         Self {
             action,
             active: false,
@@ -28,6 +27,8 @@ impl TWidget for MemoryWidget {
     fn render(
         &mut self,
         c: &Context,
+        theme: &Theme,
+        text_style: &TextStyle,
         height: i32,
         button_left_edge: f64,
         button_width: u64,
@@ -36,18 +37,12 @@ impl TWidget for MemoryWidget {
         let readings = MemoryUsage::sample();
 
         // Make text coloured if free memory is low
-        let mut scaled_bg = (readings.used as f64) / 100.0;
-        scaled_bg = 1.0 - scaled_bg.powi(2).clamp(0.0, 1.0);
-        c.set_source_rgb(1.0, scaled_bg, scaled_bg);
+        let used = (readings.used as f64) / 100.0;
+        theme.text.mix(&theme.warning, used.powi(2)).set_source(c);
 
         let text = format!("{}%", readings.used);
 
-        let text_extent = c.text_extents(&text).unwrap();
-        c.move_to(
-            button_left_edge + (button_width as f64 / 2.0 - text_extent.width() / 2.0).round(),
-            y_shift + (height as f64 / 2.0 + text_extent.height() / 2.0).round(),
-        );
-        c.show_text(&text).unwrap();
+        text_style.show(c, &text, button_left_edge, button_width, height, y_shift);
         self.last_draw_time = Instant::now();
     }
     fn set_active(&mut self, active: bool) -> bool {
@@ -60,8 +55,8 @@ impl TWidget for MemoryWidget {
         }
     }
 
-    fn get_action(&self) -> Key {
-        self.action
+    fn get_action(&self) -> &Action {
+        &self.action
     }
     fn next_draw_time(&self) -> Option<Instant> {
         Some(self.last_draw_time + Duration::from_secs(5))
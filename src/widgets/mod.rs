@@ -1,13 +1,19 @@
 mod battery;
 mod from_config;
+mod graph;
+mod icon_text_button;
 mod image_button;
 mod memory;
 mod processor;
+mod slider;
 mod text_button;
 mod time;
 mod widget_trait;
 
 pub use self::from_config::*;
+pub use self::graph::{GraphSource, GraphStyle};
+pub use self::icon_text_button::{Align, IconPosition};
+pub use self::slider::SliderSource;
 pub use self::text_button::*;
 pub use self::time::*;
 pub use self::widget_trait::*;
@@ -1,20 +1,24 @@
 use super::TWidget;
-use crate::metrics::{CPUSample, CPUUsage};
+use crate::{
+    action::Action,
+    metrics::{CPUSample, CPUUsage},
+    text::TextStyle,
+    theme::Theme,
+};
 use cairo::Context;
-use input_linux::Key;
 use std::time::{Duration, Instant};
 
 pub struct ProcessorWidget {
     last_cpu: CPUUsage,
     changed: bool,
     active: bool,
-    action: Key,
+    action: Action,
     last_sample_time: Instant,
     last_cpu_readings: CPUSample,
 }
 
 impl ProcessorWidget {
-    pub fn new(_text: String, action: Key) -> Self {
+    pub fn new(_text: String, action: Action) -> Self {
         Self {
             action,
             active: false,
@@ -30,6 +34,8 @@ impl TWidget for ProcessorWidget {
     fn render(
         &mut self,
         c: &Context,
+        theme: &Theme,
+        text_style: &TextStyle,
         height: i32,
         button_left_edge: f64,
         button_width: u64,
@@ -42,8 +48,8 @@ impl TWidget for ProcessorWidget {
         };
         // Make text coloured if load is high
         if self.last_cpu_readings.idle > 0 || self.last_cpu_readings.user > 0 {
-            let scaled_bg = (self.last_cpu_readings.idle as f64) / 100.0;
-            c.set_source_rgb(1.0, scaled_bg, scaled_bg);
+            let load = 1.0 - (self.last_cpu_readings.idle as f64) / 100.0;
+            theme.text.mix(&theme.warning, load).set_source(c);
         }
         let other_usage = 100_u8.saturating_sub(
             self.last_cpu_readings.user
@@ -58,12 +64,7 @@ impl TWidget for ProcessorWidget {
             self.last_cpu_readings.idle
         );
 
-        let text_extent = c.text_extents(&text).unwrap();
-        c.move_to(
-            button_left_edge + (button_width as f64 / 2.0 - text_extent.width() / 2.0).round(),
-            y_shift + (height as f64 / 2.0 + text_extent.height() / 2.0).round(),
-        );
-        c.show_text(&text).unwrap();
+        text_style.show(c, &text, button_left_edge, button_width, height, y_shift);
     }
     fn set_active(&mut self, active: bool) -> bool {
         if self.active != active {
@@ -75,8 +76,8 @@ impl TWidget for ProcessorWidget {
         }
     }
 
-    fn get_action(&self) -> Key {
-        self.action
+    fn get_action(&self) -> &Action {
+        &self.action
     }
     fn next_draw_time(&self) -> Option<Instant> {
         Some(self.last_sample_time + Duration::from_secs(5))
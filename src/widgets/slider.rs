@@ -0,0 +1,287 @@
+use anyhow::Result;
+use cairo::{Context, LineCap};
+use input_linux::Key;
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use super::TWidget;
+use crate::{
+    action::Action,
+    backlight::{BrightnessDevice, SYSFS_ROOT, SysfsBrightness},
+    layout::LayoutMetrics,
+    text::TextStyle,
+    theme::{Color, Theme},
+};
+
+// How often the level is read back while idle, something else may have changed it
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub enum SliderSource {
+    DisplayBacklight,
+    KeyboardBacklight,
+    // A sysfs device directory with brightness and max_brightness attributes
+    Sysfs(PathBuf),
+    // Taps Up or Down once for every 1/Steps the finger moves
+    #[serde(rename_all = "PascalCase")]
+    Keys {
+        up: Key,
+        down: Key,
+        steps: u32,
+    },
+}
+
+// What a slider reads its level from and applies it to
+pub trait SliderTarget {
+    // Current level from 0.0 to 1.0
+    fn level(&mut self) -> f64;
+    // Move to the given level, returning the keys to tap to get there
+    fn set_level(&mut self, level: f64) -> Vec<Key>;
+    fn keys(&self) -> Vec<Key> {
+        Vec::new()
+    }
+}
+
+struct BrightnessTarget {
+    device: SysfsBrightness,
+    level: f64,
+}
+
+impl SliderTarget for BrightnessTarget {
+    fn level(&mut self) -> f64 {
+        if let Ok(level) = self.device.get() {
+            self.level = level;
+        }
+        self.level
+    }
+    fn set_level(&mut self, level: f64) -> Vec<Key> {
+        match self.device.set(level) {
+            Ok(()) => self.level = level,
+            Err(err) => println!("Failed to set brightness: {err}"),
+        }
+        Vec::new()
+    }
+}
+
+// There's no way to read the level back, so this keeps its own estimate
+struct KeysTarget {
+    up: Key,
+    down: Key,
+    steps: u32,
+    step: i64,
+}
+
+impl SliderTarget for KeysTarget {
+    fn level(&mut self) -> f64 {
+        self.step as f64 / self.steps as f64
+    }
+    fn set_level(&mut self, level: f64) -> Vec<Key> {
+        let target = (level.clamp(0.0, 1.0) * self.steps as f64).round() as i64;
+        let (key, count) = if target > self.step {
+            (self.up, target - self.step)
+        } else {
+            (self.down, self.step - target)
+        };
+        self.step = target;
+        vec![key; count as usize]
+    }
+    fn keys(&self) -> Vec<Key> {
+        vec![self.up, self.down]
+    }
+}
+
+pub fn open_slider_target(source: &SliderSource, sysfs: &Path) -> Result<Box<dyn SliderTarget>> {
+    let device = match source {
+        SliderSource::Keys { up, down, steps } => {
+            let steps = (*steps).max(1);
+            return Ok(Box::new(KeysTarget {
+                up: *up,
+                down: *down,
+                steps,
+                step: steps as i64 / 2,
+            }));
+        }
+        SliderSource::DisplayBacklight => BrightnessDevice::Display,
+        SliderSource::KeyboardBacklight => BrightnessDevice::Keyboard,
+        SliderSource::Sysfs(path) => BrightnessDevice::Path(path.clone()),
+    };
+    let device = SysfsBrightness::open(sysfs, &device)?;
+    Ok(Box::new(BrightnessTarget {
+        level: device.get().unwrap_or(0.0),
+        device,
+    }))
+}
+
+pub struct SliderWidget {
+    // None when the device is missing, the slider is then drawn empty and ignores touches
+    pub target: Option<Box<dyn SliderTarget>>,
+    pub level: f64,
+    // Distance kept between the track and the button edges
+    pub inset: f64,
+    pub changed: bool,
+    pub active: bool,
+    pub action: Action,
+    pub last_poll_time: Instant,
+}
+
+impl SliderWidget {
+    pub fn new(source: &SliderSource, layout: &LayoutMetrics, action: Action) -> Self {
+        let mut target = match open_slider_target(source, Path::new(SYSFS_ROOT)) {
+            Ok(target) => Some(target),
+            Err(err) => {
+                println!("Failed to open slider target {source:?}, disabling it: {err}");
+                None
+            }
+        };
+        Self {
+            level: target.as_mut().map_or(0.0, |t| t.level()),
+            target,
+            inset: layout.corner_radius.max(layout.button_spacing / 2.0),
+            action,
+            active: false,
+            changed: false,
+            last_poll_time: Instant::now(),
+        }
+    }
+    // The level is read back while nobody is dragging it
+    fn polling(&self) -> bool {
+        self.target.is_some() && !self.active
+    }
+}
+
+impl TWidget for SliderWidget {
+    fn render(
+        &mut self,
+        c: &Context,
+        theme: &Theme,
+        _text_style: &TextStyle,
+        height: i32,
+        button_left_edge: f64,
+        button_width: u64,
+        y_shift: f64,
+    ) {
+        if let Some(target) = &mut self.target
+            && !self.active
+            && self.last_poll_time.elapsed() >= POLL_INTERVAL
+        {
+            self.level = target.level();
+            self.last_poll_time = Instant::now();
+        }
+        let thickness = (height as f64 / 6.0).round();
+        // Leave room for the knob at either end
+        let left = button_left_edge + self.inset + thickness;
+        let length = (button_width as f64 - (self.inset + thickness) * 2.0).max(0.0);
+        let middle = height as f64 / 2.0 + y_shift;
+        let knob = left + length * self.level;
+        let fill = c.source();
+
+        c.set_line_cap(LineCap::Round);
+        c.set_line_width(thickness);
+        Color {
+            a: theme.text.a * 0.3,
+            ..theme.text
+        }
+        .set_source(c);
+        c.move_to(left, middle);
+        c.line_to(left + length, middle);
+        c.stroke().unwrap();
+        if self.target.is_none() {
+            return;
+        }
+
+        c.set_source(&fill).unwrap();
+        c.move_to(left, middle);
+        c.line_to(knob, middle);
+        c.stroke().unwrap();
+        c.arc(knob, middle, thickness, 0.0, std::f64::consts::TAU);
+        c.fill().unwrap();
+    }
+    fn set_active(&mut self, active: bool) -> bool {
+        if self.active != active {
+            self.active = active;
+            self.changed = true;
+            true
+        } else {
+            false
+        }
+    }
+    fn tracks_touch(&self) -> bool {
+        true
+    }
+    fn touch_moved(&mut self, position: f64) -> Vec<Key> {
+        let level = position.clamp(0.0, 1.0);
+        let Some(target) = &mut self.target else {
+            return Vec::new();
+        };
+        if level == self.level {
+            return Vec::new();
+        }
+        self.level = level;
+        self.changed = true;
+        target.set_level(level)
+    }
+    fn keys(&self) -> Vec<Key> {
+        self.target.as_ref().map_or(Vec::new(), |t| t.keys())
+    }
+
+    fn get_action(&self) -> &Action {
+        &self.action
+    }
+    fn next_draw_time(&self) -> Option<Instant> {
+        self.polling()
+            .then_some(self.last_poll_time + POLL_INTERVAL)
+    }
+    fn changed(&self) -> bool {
+        self.changed || (self.polling() && self.last_poll_time.elapsed() >= POLL_INTERVAL)
+    }
+    fn active(&self) -> bool {
+        self.active
+    }
+
+    fn reset_changed(&mut self) {
+        self.changed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys_target(steps: u32) -> Box<dyn SliderTarget> {
+        let source = SliderSource::Keys {
+            up: Key::BrightnessUp,
+            down: Key::BrightnessDown,
+            steps,
+        };
+        open_slider_target(&source, Path::new(SYSFS_ROOT)).unwrap()
+    }
+
+    #[test]
+    fn keys_target_taps_one_key_per_step() {
+        let mut target = keys_target(10);
+        assert_eq!(target.level(), 0.5);
+        assert_eq!(target.set_level(0.8), vec![Key::BrightnessUp; 3]);
+        assert_eq!(target.set_level(0.1), vec![Key::BrightnessDown; 7]);
+        assert_eq!(target.set_level(0.12), Vec::new());
+        assert_eq!(target.set_level(-1.0), vec![Key::BrightnessDown; 1]);
+        assert_eq!(target.set_level(5.0), vec![Key::BrightnessUp; 10]);
+        assert_eq!(target.level(), 1.0);
+    }
+
+    #[test]
+    fn keys_target_has_at_least_one_step() {
+        let mut target = keys_target(0);
+        assert_eq!(target.set_level(1.0), vec![Key::BrightnessUp; 1]);
+        assert_eq!(target.set_level(0.0), vec![Key::BrightnessDown; 1]);
+    }
+
+    #[test]
+    fn missing_device_is_an_error() {
+        let root = std::env::temp_dir().join("tiny-dfr-no-such-sysfs");
+        assert!(open_slider_target(&SliderSource::KeyboardBacklight, &root).is_err());
+    }
+}
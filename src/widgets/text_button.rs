@@ -1,17 +1,17 @@
 use super::TWidget;
+use crate::{action::Action, text::TextStyle, theme::Theme};
 use cairo::Context;
-use input_linux::Key;
 use std::time::Instant;
 
 pub struct TextButton {
     pub text: String,
     pub changed: bool,
     pub active: bool,
-    pub action: Key,
+    pub action: Action,
 }
 
 impl TextButton {
-    pub fn new(text: &str, action: Key) -> Self {
+    pub fn new(text: &str, action: Action) -> Self {
         Self {
             action,
             active: false,
@@ -25,17 +25,21 @@ impl TWidget for TextButton {
     fn render(
         &mut self,
         c: &Context,
+        _theme: &Theme,
+        text_style: &TextStyle,
         height: i32,
         button_left_edge: f64,
         button_width: u64,
         y_shift: f64,
     ) {
-        let extents = c.text_extents(&self.text).unwrap();
-        c.move_to(
-            button_left_edge + (button_width as f64 / 2.0 - extents.width() / 2.0).round(),
-            y_shift + (height as f64 / 2.0 + extents.height() / 2.0).round(),
+        text_style.show(
+            c,
+            &self.text,
+            button_left_edge,
+            button_width,
+            height,
+            y_shift,
         );
-        c.show_text(&self.text).unwrap();
     }
     fn set_active(&mut self, active: bool) -> bool {
         if self.active != active {
@@ -47,12 +51,20 @@ impl TWidget for TextButton {
         }
     }
 
-    fn get_action(&self) -> Key {
-        self.action
+    fn get_action(&self) -> &Action {
+        &self.action
     }
     fn next_draw_time(&self) -> Option<Instant> {
         None
     }
+    fn text(&self) -> Option<&str> {
+        Some(&self.text)
+    }
+    fn set_text(&mut self, text: &str) -> bool {
+        self.text = text.to_owned();
+        self.changed = true;
+        true
+    }
     fn changed(&self) -> bool {
         self.changed
     }
@@ -1,7 +1,7 @@
 use super::TWidget;
+use crate::{action::Action, text::TextStyle, theme::Theme};
 use cairo::Context;
 use chrono::{Local, Locale, Timelike};
-use input_linux::Key;
 use std::time::{Duration, Instant};
 
 pub struct TimeWidget {
@@ -9,11 +9,11 @@ pub struct TimeWidget {
     pub locale: String,
     pub changed: bool,
     pub active: bool,
-    pub action: Key,
+    pub action: Action,
 }
 
 impl TimeWidget {
-    pub fn new(format: String, locale: Option<String>, action: Key) -> Self {
+    pub fn new(format: String, locale: Option<String>, action: Action) -> Self {
         let locale = match locale {
             Some(l) => l,
             None => "POSIX".to_owned(),
@@ -32,6 +32,8 @@ impl TWidget for TimeWidget {
     fn render(
         &mut self,
         c: &Context,
+        _theme: &Theme,
+        text_style: &TextStyle,
         height: i32,
         button_left_edge: f64,
         button_width: u64,
@@ -43,12 +45,14 @@ impl TWidget for TimeWidget {
             .format_localized(&self.format, current_locale)
             .to_string();
 
-        let time_extents = c.text_extents(&formatted_time).unwrap();
-        c.move_to(
-            button_left_edge + (button_width as f64 / 2.0 - time_extents.width() / 2.0).round(),
-            y_shift + (height as f64 / 2.0 + time_extents.height() / 2.0).round(),
+        text_style.show(
+            c,
+            &formatted_time,
+            button_left_edge,
+            button_width,
+            height,
+            y_shift,
         );
-        c.show_text(&formatted_time).unwrap();
     }
     fn set_active(&mut self, active: bool) -> bool {
         if self.active != active {
@@ -60,8 +64,8 @@ impl TWidget for TimeWidget {
         }
     }
 
-    fn get_action(&self) -> Key {
-        self.action
+    fn get_action(&self) -> &Action {
+        &self.action
     }
     fn next_draw_time(&self) -> Option<Instant> {
         let now = Local::now();
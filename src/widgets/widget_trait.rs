@@ -1,13 +1,23 @@
+use anyhow::Result;
 use cairo::Context;
-use input_linux::{EventKind, Key, SynchronizeKind, UInputHandle};
-use std::{os::fd::AsRawFd, time::Instant};
+use input_linux::Key;
+use std::time::Instant;
 
-use crate::emit;
+use crate::{
+    action::{Action, KeyEvent, Repeat, VirtualKeyboard},
+    text::TextStyle,
+    theme::Theme,
+};
 
 pub trait TWidget {
+    // The source is set to the theme's text color, or pressed text color
+    // while active, before this is called
+    #[allow(clippy::too_many_arguments)]
     fn render(
         &mut self,
         c: &Context,
+        theme: &Theme,
+        text_style: &TextStyle,
         height: i32,
         button_left_edge: f64,
         button_width: u64,
@@ -17,34 +27,63 @@ pub trait TWidget {
     fn next_draw_time(&self) -> Option<Instant>;
     // Used for active / key up-down events
     fn set_active(&mut self, active: bool) -> bool;
-    fn get_action(&self) -> Key;
+    fn get_action(&self) -> &Action;
+    // Widgets that follow the finger don't send their key on touch, they get
+    // touch_moved with the position across the button instead, from 0.0 at the
+    // left edge to 1.0 at the right, and return the keys to tap
+    fn tracks_touch(&self) -> bool {
+        false
+    }
+    fn touch_moved(&mut self, _position: f64) -> Vec<Key> {
+        Vec::new()
+    }
+    // Every key this widget may send, so they can be registered with uinput
+    fn keys(&self) -> Vec<Key> {
+        self.get_action().keys()
+    }
+    // Changed at runtime over the control socket, widgets without a label or
+    // icon return false
+    fn text(&self) -> Option<&str> {
+        None
+    }
+    fn set_text(&mut self, _text: &str) -> bool {
+        false
+    }
+    fn set_icon(&mut self, _icon: &str, _theme: Option<&str>) -> Result<bool> {
+        Ok(false)
+    }
     fn changed(&self) -> bool;
     fn active(&self) -> bool;
     fn reset_changed(&mut self);
 }
 
-pub fn set_widget_active<F>(
+pub fn set_widget_active(
     widget: &mut Box<dyn TWidget>,
-    uinput: &mut UInputHandle<F>,
+    repeat: Option<Repeat>,
+    keyboard: &mut VirtualKeyboard,
     active: bool,
-) where
-    F: AsRawFd,
-{
-    if widget.set_active(active) {
+) {
+    if widget.set_active(active) && !widget.tracks_touch() {
         //Active changed
-        toggle_key(uinput, widget.get_action(), active as i32);
+        let action = widget.get_action();
+        if active {
+            let mut events = action.press();
+            if let (Some(repeat), Some(key)) = (repeat, action.repeat_key()) {
+                events.push(KeyEvent::Repeat(key, repeat));
+            }
+            keyboard.send(events);
+        } else {
+            keyboard.send(action.release());
+        }
     }
 }
 
-fn toggle_key<F>(uinput: &mut UInputHandle<F>, code: Key, value: i32)
-where
-    F: AsRawFd,
-{
-    emit(uinput, EventKind::Key, code as u16, value);
-    emit(
-        uinput,
-        EventKind::Synchronize,
-        SynchronizeKind::Report as u16,
-        0,
-    );
+pub fn move_widget_touch(
+    widget: &mut Box<dyn TWidget>,
+    keyboard: &mut VirtualKeyboard,
+    position: f64,
+) {
+    for key in widget.touch_moved(position) {
+        keyboard.tap(key);
+    }
 }